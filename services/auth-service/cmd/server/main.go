@@ -114,6 +114,9 @@ func main() {
 	sessionsRoutes.Post("/:id/join", sessionHandler.JoinSession)
 	sessionsRoutes.Post("/:id/rate", ratingHandler.RateSession)
 
+	internalRoutes := v1.Group("/internal", api.InternalAuthMiddleware())
+	internalRoutes.Get("/sessions/:id/members/:userId", sessionHandler.GetSessionMembership)
+
 	port := os.Getenv("APP_PORT")
 	if port == "" {
 		port = "8001"
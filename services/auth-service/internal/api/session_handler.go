@@ -167,6 +167,34 @@ func (h *SessionHandler) GetSessionDetails(c *fiber.Ctx) error {
 	return c.Status(fiber.StatusOK).JSON(session)
 }
 
+// GetSessionMembership answers realtime-service's admission check: 200 with
+// the session's coach when the user is the coach or a participant, 404 otherwise.
+func (h *SessionHandler) GetSessionMembership(c *fiber.Ctx) error {
+	sessionID, err := uuid.Parse(c.Params("id"))
+	if err != nil {
+		return c.Status(fiber.StatusBadRequest).JSON(fiber.Map{"error": "Invalid session ID format"})
+	}
+	userID, err := uuid.Parse(c.Params("userId"))
+	if err != nil {
+		return c.Status(fiber.StatusBadRequest).JSON(fiber.Map{"error": "Invalid user ID format"})
+	}
+
+	session, err := h.sessionService.GetMembership(c.Context(), sessionID, userID)
+	if err != nil {
+		if errors.Is(err, service.ErrSessionNotFound) || errors.Is(err, service.ErrNotAMember) {
+			return c.Status(fiber.StatusNotFound).JSON(fiber.Map{"error": "Not a member of this session"})
+		}
+		slog.ErrorContext(c.UserContext(), "Error checking session membership", slog.String("error", err.Error()))
+		return c.Status(fiber.StatusInternalServerError).JSON(fiber.Map{"error": "Could not check session membership"})
+	}
+
+	return c.Status(fiber.StatusOK).JSON(fiber.Map{
+		"session_id": session.ID,
+		"user_id":    userID,
+		"coach_id":   session.CoachID,
+	})
+}
+
 func (h *SessionHandler) GetUserProfile(c *fiber.Ctx) error {
 	userID, err := GetUserIDFromClaims(c)
 	if err != nil {
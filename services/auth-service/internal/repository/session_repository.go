@@ -27,6 +27,7 @@ type SessionRepository interface {
 	FindByID(ctx context.Context, sessionID uuid.UUID) (*model.Session, error)
 	AddParticipant(ctx context.Context, sessionID, userID uuid.UUID, role string) error
 	CountParticipants(ctx context.Context, sessionID uuid.UUID) (int, error)
+	IsParticipant(ctx context.Context, sessionID, userID uuid.UUID) (bool, error)
 	ListUpcoming(ctx context.Context, categoryID string, page int, limit int) (*PaginatedSessions, error)
 	ListHistoryByUserID(ctx context.Context, userID uuid.UUID) ([]model.SessionDetails, error)
 	GetCategories(ctx context.Context) ([]model.Category, error)
@@ -94,6 +95,18 @@ func (r *postgresSessionRepository) CountParticipants(ctx context.Context, sessi
 	return count, nil
 }
 
+func (r *postgresSessionRepository) IsParticipant(ctx context.Context, sessionID, userID uuid.UUID) (bool, error) {
+	var exists bool
+	query := `SELECT EXISTS(SELECT 1 FROM session_participants WHERE session_id = $1 AND user_id = $2)`
+	err := r.db.GetContext(ctx, &exists, query, sessionID, userID)
+
+	if err != nil {
+		return false, err
+	}
+
+	return exists, nil
+}
+
 func (r *postgresSessionRepository) ListUpcoming(ctx context.Context, categoryID string, page int, limit int) (*PaginatedSessions, error) {
 	offset := (page - 1) * limit
 
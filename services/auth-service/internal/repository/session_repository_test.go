@@ -81,3 +81,21 @@ func TestPostgresSessionRepository_AddParticipantAndCount(t *testing.T) {
 
 	require.NoError(t, mock.ExpectationsWereMet())
 }
+
+func TestPostgresSessionRepository_IsParticipant(t *testing.T) {
+	db, mock, err := sqlmock.New()
+	require.NoError(t, err)
+	defer db.Close()
+
+	sqlxDB := sqlx.NewDb(db, "sqlmock")
+	r := repo.NewPostgresSessionRepository(sqlxDB)
+
+	sessionID, userID := uuid.New(), uuid.New()
+	mock.ExpectQuery(regexp.QuoteMeta(`SELECT EXISTS(SELECT 1 FROM session_participants WHERE session_id = $1 AND user_id = $2)`)).
+		WithArgs(sessionID, userID).WillReturnRows(sqlmock.NewRows([]string{"exists"}).AddRow(true))
+
+	ok, err := r.IsParticipant(context.Background(), sessionID, userID)
+	require.NoError(t, err)
+	require.True(t, ok)
+	require.NoError(t, mock.ExpectationsWereMet())
+}
@@ -14,6 +14,7 @@ var (
 	ErrSessionNotFound = errors.New("session not found")
 	ErrAlreadyJoined   = errors.New("user has already joined this session")
 	ErrSessionFull     = errors.New("session is full")
+	ErrNotAMember      = errors.New("user is not a member of this session")
 )
 
 type SessionService interface {
@@ -22,6 +23,7 @@ type SessionService interface {
 	ListUpcomingSessions(ctx context.Context, categoryID string, page int, limit int) (*repository.PaginatedSessions, error)
 	ListUserHistory(ctx context.Context, userID uuid.UUID) ([]model.SessionDetails, error)
 	GetSessionDetails(ctx context.Context, sessionID uuid.UUID) (*model.Session, error)
+	GetMembership(ctx context.Context, sessionID, userID uuid.UUID) (*model.Session, error)
 	GetCategories(ctx context.Context) ([]model.Category, error)
 }
 
@@ -97,6 +99,26 @@ func (s *sessionService) GetSessionDetails(ctx context.Context, sessionID uuid.U
 	return session, nil
 }
 
+// GetMembership returns the session if userID is its coach or a participant.
+func (s *sessionService) GetMembership(ctx context.Context, sessionID, userID uuid.UUID) (*model.Session, error) {
+	session, err := s.GetSessionDetails(ctx, sessionID)
+	if err != nil {
+		return nil, err
+	}
+	if session.CoachID == userID {
+		return session, nil
+	}
+
+	isParticipant, err := s.sessionRepo.IsParticipant(ctx, sessionID, userID)
+	if err != nil {
+		return nil, err
+	}
+	if !isParticipant {
+		return nil, ErrNotAMember
+	}
+	return session, nil
+}
+
 func (s *sessionService) GetCategories(ctx context.Context) ([]model.Category, error) {
 	return s.sessionRepo.GetCategories(ctx)
 }
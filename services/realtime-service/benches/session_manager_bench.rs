@@ -1,4 +1,5 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
+use realtime_service::auth::jwt::Claims;
 use realtime_service::services::session_manager::{SessionManager, Connection};
 use tokio::sync::mpsc;
 use uuid::Uuid;
@@ -6,12 +7,16 @@ use std::sync::Arc;
 
 fn create_connection() -> Connection {
     let (tx, _rx) = mpsc::channel(16);
-    Connection { sender: tx }
+    let user_info = Claims {
+        sub: Uuid::new_v4(),
+        name: "Bench".to_string(),
+        email: "bench@example.com".to_string(),
+        exp: usize::MAX,
+    };
+    Connection { sender: tx, user_info, closer: None }
 }
 
 fn bench_insert_single(c: &mut Criterion) {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    
     c.bench_function("insert_single_connection", |b| {
         b.iter(|| {
             let manager = SessionManager::new();
@@ -21,7 +26,8 @@ fn bench_insert_single(c: &mut Criterion) {
             manager.insert(
                 black_box(session_id),
                 black_box(conn_id),
-                create_connection()
+                create_connection(),
+                None
             );
         });
     });
@@ -40,7 +46,8 @@ fn bench_insert_multiple(c: &mut Criterion) {
                     manager.insert(
                         session_id,
                         i,
-                        create_connection()
+                        create_connection(),
+                        None
                     );
                 }
             });
@@ -51,7 +58,6 @@ fn bench_insert_multiple(c: &mut Criterion) {
 }
 
 fn bench_broadcast(c: &mut Criterion) {
-    let rt = tokio::runtime::Runtime::new().unwrap();
     let mut group = c.benchmark_group("broadcast");
     
     for conn_count in [10, 50, 100, 500].iter() {
@@ -64,7 +70,7 @@ fn bench_broadcast(c: &mut Criterion) {
                 
                 // Setup connections
                 for i in 0..conn_count {
-                    manager.insert(session_id, i, create_connection());
+                    manager.insert(session_id, i, create_connection(), None);
                 }
                 
                 b.iter(|| {
@@ -98,7 +104,8 @@ fn bench_concurrent_inserts(c: &mut Criterion) {
                         manager_clone.insert(
                             session_id,
                             i,
-                            create_connection()
+                            create_connection(),
+                            None
                         );
                     });
                     handles.push(handle);
@@ -123,7 +130,7 @@ fn bench_remove(c: &mut Criterion) {
                     let session_id = Uuid::new_v4();
                     
                     for i in 0..size {
-                        manager.insert(session_id, i, create_connection());
+                        manager.insert(session_id, i, create_connection(), None);
                     }
                     
                     (manager, session_id)
@@ -142,8 +149,6 @@ fn bench_remove(c: &mut Criterion) {
 }
 
 fn bench_mixed_operations(c: &mut Criterion) {
-    let rt = tokio::runtime::Runtime::new().unwrap();
-    
     c.bench_function("mixed_operations", |b| {
         b.iter(|| {
             let manager = SessionManager::new();
@@ -151,7 +156,7 @@ fn bench_mixed_operations(c: &mut Criterion) {
             
             // Insert 50 connections
             for i in 0..50 {
-                manager.insert(session_id, i, create_connection());
+                manager.insert(session_id, i, create_connection(), None);
             }
             
             // Broadcast 10 messages
@@ -180,7 +185,7 @@ fn bench_session_churn(c: &mut Criterion) {
             // Create and destroy 1000 sessions
             for _ in 0..1000 {
                 let session_id = Uuid::new_v4();
-                manager.insert(session_id, 1, create_connection());
+                manager.insert(session_id, 1, create_connection(), None);
                 manager.remove(session_id, 1);
             }
         });
@@ -202,7 +207,7 @@ fn bench_lock_contention(c: &mut Criterion) {
                 for i in 0..50 {
                     let manager_clone = Arc::clone(&manager);
                     let handle = tokio::spawn(async move {
-                        manager_clone.insert(session_id, i, create_connection());
+                        manager_clone.insert(session_id, i, create_connection(), None);
                         manager_clone.broadcast_message(session_id, "test", None);
                         manager_clone.remove(session_id, i);
                     });
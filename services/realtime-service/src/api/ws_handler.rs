@@ -1,31 +1,715 @@
 use crate::{
-    auth::jwt,
+    auth::jwt::{self, Claims},
     events::nats_publisher::NatsPublisher,
-    model::chat_message::{BroadcastMessage, ChatMessage, SenderInfo},
-    services::session_manager::{Connection, SessionManager}
+    model::chat_message::{BroadcastMessage, ChatMessage, DirectMessage, SenderInfo},
+    model::protocol::{ChatMode, ClientEnvelope, ClientMessage, CloseCode, ErrorCode, ModerationAction, ServerMessage, PROTOCOL_VERSION},
+    services::session_manager::{Connection, DeliveryReceipt, MessageChangeError, ReactionError, Replay, SessionManager, StampedMessage},
+    services::content_validation::{ContentError, ContentLimits},
+    services::sanitizer::strip_html,
+    services::membership::MembershipLookup,
+    services::message_filter::{FilterFlag, FilterPipeline, FilterVerdict},
+    services::session_registry::{ChatSettings, SessionRegistry}
 };
 use actix_web::{web, Error, HttpRequest, HttpResponse};
-use actix_ws::Message;
+use actix_ws::{CloseReason, Closed, Message, ProtocolError};
+use chrono::{DateTime, Utc};
 use futures_util::StreamExt;
-use serde::Deserialize;
-use std::time::Duration;
-use tokio::time::interval;
-use tokio::sync::mpsc;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{interval, sleep};
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+/// JSON body of an upgrade refused because of the session's lifecycle.
+#[derive(Serialize)]
+struct SessionUnavailableResponse {
+    code: ErrorCode,
+    message: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_at: Option<DateTime<Utc>>
+}
 
 #[derive(Deserialize)]
 pub struct WsConnectQuery {
-    token: String
+    token: String,
+    /// Highest `seq` the client saw before reconnecting; the gap is replayed.
+    last_seq: Option<u64>
 }
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// Consecutive unparseable frames tolerated before the connection is closed
+/// with `CloseCode::ProtocolViolation`.
+const MAX_PROTOCOL_ERRORS: u32 = 5;
+/// Consecutive rate-limited or rejected posts tolerated before the
+/// connection is closed with `CloseCode::RateLimited`.
+const MAX_RATE_VIOLATIONS: u32 = 10;
+/// Longest mute a coach can impose in one go.
+const MAX_MUTE_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+/// Longest reason accepted on an abuse report, in characters.
+const MAX_REPORT_REASON_CHARS: usize = 500;
+/// Most users a single chat message may mention.
+const MAX_MENTIONS: usize = 20;
+
+async fn send_error(
+    session: &mut actix_ws::Session,
+    code: ErrorCode,
+    message: &str,
+    client_msg_id: Option<String>
+) -> Result<(), Closed> {
+    let frame = ServerMessage::error(code, message, client_msg_id);
+    return session.text(frame.to_json()).await;
+}
+
+/// Sends a handler's reply, if it has one.
+async fn send_reply(session: &mut actix_ws::Session, reply: Option<&ServerMessage>) -> Result<(), Closed> {
+    return match reply {
+        Some(reply) => session.text(reply.to_json()).await,
+        None => Ok(()),
+    };
+}
+
+async fn close_with(session: actix_ws::Session, code: CloseCode) {
+    let reason = CloseReason {
+        code: actix_ws::CloseCode::Other(code.code()),
+        description: Some(code.description().to_string()),
+    };
+
+    if session.close(Some(reason)).await.is_err() {
+        eprintln!("❌ Failed to send close frame ({:?})", code);
+    }
+}
+
+/// Everything an inbound message handler needs to act on behalf of one connection.
+struct ConnectionContext {
+    session_id: Uuid,
+    conn_id: usize,
+    manager: web::Data<SessionManager>,
+    publisher: web::Data<NatsPublisher>,
+    registry: web::Data<SessionRegistry>,
+    limits: web::Data<ContentLimits>,
+    filters: web::Data<FilterPipeline>
+}
+
+/// `nack` for content refused by `ContentLimits::sanitize`.
+fn content_error(error: ContentError, client_msg_id: Option<String>) -> ServerMessage {
+    let (code, message) = match error {
+        ContentError::Empty => (ErrorCode::EmptyContent, "Message content cannot be empty".to_string()),
+        ContentError::TooLong { max_chars } => (
+            ErrorCode::ContentTooLong,
+            format!("Message content cannot exceed {} characters", max_chars)
+        ),
+        ContentError::ControlCharacter => (
+            ErrorCode::InvalidCharacters,
+            "Message content cannot contain control characters".to_string()
+        ),
+        ContentError::UnsafeLink { scheme } => (
+            ErrorCode::UnsafeLink,
+            format!("Links using {}: are not allowed", scheme)
+        ),
+    };
+    return ServerMessage::nack(code, message, client_msg_id);
+}
+
+/// The `error` frame carrying a `nack`'s code, message and retry delay, for
+/// operations such as edits that are answered with errors rather than acks.
+fn nack_to_error(reply: ServerMessage) -> ServerMessage {
+    return match reply {
+        ServerMessage::Nack { client_msg_id, code, message, retry_after_ms } => {
+            ServerMessage::Error { code, message, client_msg_id, retry_after_ms }
+        }
+        other => other,
+    };
+}
+
+/// `nack` for content a moderation filter refused.
+fn filter_rejection(ctx: &ConnectionContext, user: &Claims, filter: &str, reason: String, client_msg_id: Option<String>) -> ServerMessage {
+    println!("🛡️  Filter {} rejected content from user {} in session {}", filter, user.sub, ctx.session_id);
+    return ServerMessage::nack(ErrorCode::ContentRejected, reason, client_msg_id);
+}
+
+/// Reports a delivered message that filters flagged for review. The message
+/// is already out, so failures are only logged.
+async fn publish_flags(
+    ctx: &ConnectionContext,
+    message_id: Uuid,
+    author: &SenderInfo,
+    content: &str,
+    is_private: bool,
+    flags: &[FilterFlag]
+) {
+    if flags.is_empty() {
+        return;
+    }
+
+    if let Err(e) = ctx.publisher.publish_message_flagged(ctx.session_id, message_id, author, content, is_private, flags).await {
+        eprintln!("❌ Failed to publish review flag for message {}: {}", message_id, e);
+    }
+}
+
+/// Stamps, publishes and fans out a chat message, returning the `ack` or
+/// `nack` frame for the sender. Nothing is broadcast unless the NATS publish
+/// succeeded, so an `ack` means the message will also be persisted. A retry
+/// carrying an already accepted `client_msg_id` gets the original `ack` back
+/// before any other check, without being broadcast or published again.
+async fn handle_chat(ctx: &ConnectionContext, user: &Claims, mut chat_msg: ChatMessage, client_msg_id: Option<String>) -> ServerMessage {
+    if let Some(key) = client_msg_id.as_deref()
+        && let Some(receipt) = ctx.manager.delivery_receipt(ctx.session_id, user.sub, key) {
+        println!("♻️  Duplicate submission {:?} from conn_id={}, replaying ack", client_msg_id, ctx.conn_id);
+        return ServerMessage::ack(client_msg_id, &receipt);
+    }
+
+    if let Some(nack) = reject_if_muted(ctx, user, &client_msg_id) {
+        return nack;
+    }
+
+    let is_coach = ctx.registry.is_coach(ctx.session_id, user.sub);
+    if let Some(nack) = reject_if_restricted(ctx, user, is_coach, &client_msg_id) {
+        return nack;
+    }
+
+    if let Err(retry_after) = check_post_rate(ctx, user.sub, is_coach) {
+        return ServerMessage::rate_limited(retry_after, client_msg_id);
+    }
+
+    let sanitized = match ctx.limits.sanitize(&chat_msg.content) {
+        Ok(sanitized) => sanitized,
+        Err(e) => return content_error(e, client_msg_id),
+    };
+
+    let flags = match ctx.filters.apply(sanitized) {
+        FilterVerdict::Deliver { content, flags } => {
+            chat_msg.content = content.content;
+            chat_msg.links = content.links;
+            flags
+        }
+        FilterVerdict::Reject { filter, reason } => return filter_rejection(ctx, user, &filter, reason, client_msg_id),
+    };
+
+    if chat_msg.mentions.len() > MAX_MENTIONS {
+        return ServerMessage::nack(
+            ErrorCode::InvalidMention,
+            format!("A message can mention at most {} users", MAX_MENTIONS),
+            client_msg_id
+        );
+    }
+
+    if let Some(user_id) = chat_msg.mentions.iter().find(|user_id| !is_session_member(ctx, **user_id)) {
+        return ServerMessage::nack(
+            ErrorCode::InvalidMention,
+            format!("User {} is not a member of this session", user_id),
+            client_msg_id
+        );
+    }
+
+    let stamped = ctx.manager.get_user_info(ctx.session_id, ctx.conn_id).and_then(|sender_info| {
+        let sender = SenderInfo {
+            id: sender_info.sub,
+            name: sender_info.name,
+        };
+        ctx.manager.stamp_chat_message(ctx.session_id, sender, chat_msg, client_msg_id.as_deref())
+    });
+
+    let chat_message = match stamped {
+        Some(StampedMessage::Fresh(chat_message)) => chat_message,
+        Some(StampedMessage::Duplicate(receipt)) => {
+            println!("♻️  Duplicate submission {:?} from conn_id={}, replaying ack", client_msg_id, ctx.conn_id);
+            return ServerMessage::ack(client_msg_id, &receipt);
+        }
+        None => {
+            eprintln!("❌ Could not find sender info for conn_id={}", ctx.conn_id);
+            return ServerMessage::nack(
+                ErrorCode::NotInSession,
+                "Connection is not registered in this session",
+                client_msg_id
+            );
+        }
+    };
+
+    if let Err(e) = ctx.publisher.publish_chat_message(ctx.session_id, &chat_message).await {
+        eprintln!("❌ Dropping message {} after publish failure: {}", chat_message.id, e);
+        if let Some(key) = client_msg_id.as_deref() {
+            ctx.manager.forget_idempotency_key(ctx.session_id, chat_message.sender.id, key);
+        }
+
+        return ServerMessage::nack(
+            ErrorCode::PublishFailed,
+            "Message could not be delivered, please retry",
+            client_msg_id
+        );
+    }
+
+    publish_mentions(ctx, &chat_message).await;
+    publish_flags(ctx, chat_message.id, &chat_message.sender, &chat_message.content, false, &flags).await;
+
+    let ack = ServerMessage::ack(client_msg_id, &DeliveryReceipt::from(&chat_message));
+
+    println!("📡 Broadcasting message {} (seq {})", chat_message.id, chat_message.seq);
+    ctx.manager.broadcast_chat_message(ctx.session_id, chat_message, Some(ctx.conn_id));
+
+    return ack;
+}
+
+/// `nack` for a user who is still muted in this session.
+fn reject_if_muted(ctx: &ConnectionContext, user: &Claims, client_msg_id: &Option<String>) -> Option<ServerMessage> {
+    let until = ctx.registry.muted_until(ctx.session_id, user.sub)?;
+    return Some(ServerMessage::nack(
+        ErrorCode::Muted,
+        format!("You are muted until {}", until.to_rfc3339()),
+        client_msg_id.clone()
+    ));
+}
+
+/// `nack` for a user the session's chat mode keeps from posting.
+fn reject_if_restricted(ctx: &ConnectionContext, user: &Claims, is_coach: bool, client_msg_id: &Option<String>) -> Option<ServerMessage> {
+    let message = match ctx.registry.post_restriction(ctx.session_id, user.sub, is_coach)? {
+        ChatMode::AnnouncementOnly => "Only the coach and co-hosts can post right now",
+        _ => "Chat is disabled in this session",
+    };
+    return Some(ServerMessage::nack(ErrorCode::ChatRestricted, message, client_msg_id.clone()));
+}
+
+/// Counts a post against the rate limits and, unless `user_id` is exempt,
+/// slow mode.
+fn check_post_rate(ctx: &ConnectionContext, user_id: Uuid, is_coach: bool) -> Result<(), Duration> {
+    let slow_mode = ctx.registry.slow_mode_for(ctx.session_id, user_id, is_coach);
+    return ctx.manager.check_rate(ctx.session_id, user_id, slow_mode);
+}
+
+/// Whether a post was refused by the rate limiter or for its content, which
+/// counts towards `MAX_RATE_VIOLATIONS`.
+fn is_rate_limited(reply: &ServerMessage) -> bool {
+    return matches!(reply, ServerMessage::Nack {
+        code: ErrorCode::RateLimited
+            | ErrorCode::EmptyContent
+            | ErrorCode::ContentTooLong
+            | ErrorCode::InvalidCharacters
+            | ErrorCode::UnsafeLink
+            | ErrorCode::ContentRejected,
+        ..
+    });
+}
+
+/// Announces the session's new chat settings to every connection.
+fn broadcast_chat_settings(ctx: &ConnectionContext, settings: &ChatSettings) {
+    ctx.manager.broadcast_message(ctx.session_id, &settings.to_message().to_json(), None);
+}
+
+/// Members are known from auth-service events; users connected right now
+/// count too, in case those events were missed.
+fn is_session_member(ctx: &ConnectionContext, user_id: Uuid) -> bool {
+    return ctx.registry.is_member(ctx.session_id, user_id)
+        || ctx.manager.is_user_connected(ctx.session_id, user_id);
+}
+
+/// Publishes one `chat.mention` event per mentioned user other than the
+/// sender. The message itself is already persisted, so failures are only logged.
+async fn publish_mentions(ctx: &ConnectionContext, message: &BroadcastMessage) {
+    for &user_id in message.mentions.iter().filter(|&&user_id| user_id != message.sender.id) {
+        let is_connected = ctx.manager.is_user_connected(ctx.session_id, user_id);
+        if let Err(e) = ctx.publisher.publish_mention(ctx.session_id, message, user_id, is_connected).await {
+            eprintln!("❌ Failed to publish mention of user {} in message {}: {}", user_id, message.id, e);
+        }
+    }
+}
+
+/// Publishes a private message and delivers it to the recipient's and the
+/// sender's other connections, returning the `ack` or `nack` for the sender.
+/// Retries are deduplicated by `client_msg_id` the same way as chat messages.
+async fn handle_direct_message(
+    ctx: &ConnectionContext,
+    user: &Claims,
+    to_user_id: Uuid,
+    content: String,
+    client_msg_id: Option<String>
+) -> ServerMessage {
+    if let Some(key) = client_msg_id.as_deref()
+        && let Some(receipt) = ctx.manager.delivery_receipt(ctx.session_id, user.sub, key) {
+        println!("♻️  Duplicate direct message {:?} from conn_id={}, replaying ack", client_msg_id, ctx.conn_id);
+        return ServerMessage::ack(client_msg_id, &receipt);
+    }
+
+    if let Some(nack) = reject_if_muted(ctx, user, &client_msg_id) {
+        return nack;
+    }
+
+    if to_user_id == user.sub || !is_session_member(ctx, to_user_id) {
+        return ServerMessage::nack(
+            ErrorCode::InvalidRecipient,
+            "Direct messages must be sent to another member of this session",
+            client_msg_id
+        );
+    }
+
+    let is_coach = ctx.registry.is_coach(ctx.session_id, user.sub);
+    if let Err(retry_after) = check_post_rate(ctx, user.sub, is_coach) {
+        return ServerMessage::rate_limited(retry_after, client_msg_id);
+    }
+
+    let content = match ctx.limits.sanitize(&content) {
+        Ok(content) => content,
+        Err(e) => return content_error(e, client_msg_id),
+    };
+
+    let (content, flags) = match ctx.filters.apply(content) {
+        FilterVerdict::Deliver { content, flags } => (content, flags),
+        FilterVerdict::Reject { filter, reason } => return filter_rejection(ctx, user, &filter, reason, client_msg_id),
+    };
+
+    let message = DirectMessage {
+        id: Uuid::new_v4(),
+        sent_at: Utc::now(),
+        sender: SenderInfo { id: user.sub, name: user.name.clone() },
+        to_user_id,
+        content: content.content,
+        links: content.links,
+    };
+
+    if let Some(key) = client_msg_id.as_deref()
+        && let Some(receipt) = ctx.manager.claim_idempotency_key(ctx.session_id, user.sub, key, DeliveryReceipt::from(&message)) {
+        println!("♻️  Duplicate direct message {:?} from conn_id={}, replaying ack", client_msg_id, ctx.conn_id);
+        return ServerMessage::ack(client_msg_id, &receipt);
+    }
+
+    if let Err(e) = ctx.publisher.publish_direct_message(ctx.session_id, &message).await {
+        eprintln!("❌ Dropping direct message {} after publish failure: {}", message.id, e);
+        if let Some(key) = client_msg_id.as_deref() {
+            ctx.manager.forget_idempotency_key(ctx.session_id, user.sub, key);
+        }
+
+        return ServerMessage::nack(
+            ErrorCode::PublishFailed,
+            "Message could not be delivered, please retry",
+            client_msg_id
+        );
+    }
+
+    publish_flags(ctx, message.id, &message.sender, &message.content, true, &flags).await;
+    ctx.manager.deliver_direct_message(ctx.session_id, &message, Some(ctx.conn_id));
+
+    return ServerMessage::ack(client_msg_id, &DeliveryReceipt::from(&message));
+}
+
+/// Records a read receipt and publishes it for persistence when the user's
+/// high-water mark actually moved. Returns the error frame for the
+/// requester, if any.
+async fn handle_read_up_to(ctx: &ConnectionContext, user: &Claims, seq: u64, client_msg_id: Option<String>) -> Option<ServerMessage> {
+    if let Err(retry_after) = ctx.manager.check_action_rate(ctx.session_id, user.sub) {
+        return Some(nack_to_error(ServerMessage::rate_limited(retry_after, client_msg_id)));
+    }
+
+    if let Some(read_seq) = ctx.manager.mark_read(ctx.session_id, user, seq)
+        && let Err(e) = ctx.publisher.publish_read_receipt(ctx.session_id, user.sub, read_seq).await {
+        eprintln!("❌ Failed to publish read receipt for conn_id={}: {}", ctx.conn_id, e);
+    }
+    return None;
+}
+
+fn message_change_error(error: MessageChangeError, client_msg_id: Option<String>) -> ServerMessage {
+    return match error {
+        MessageChangeError::NotFound => ServerMessage::error(ErrorCode::MessageNotFound, "Message not found", client_msg_id),
+        MessageChangeError::Forbidden => ServerMessage::error(
+            ErrorCode::Forbidden,
+            "Only the sender or the session's coach can change this message",
+            client_msg_id
+        ),
+    };
+}
+
+/// Edits a buffered message once the change is published, so persisted
+/// history is never behind what clients were shown. Returns the error frame
+/// for the requester, if any.
+async fn handle_edit(
+    ctx: &ConnectionContext,
+    user: &Claims,
+    message_id: Uuid,
+    content: String,
+    client_msg_id: Option<String>
+) -> Option<ServerMessage> {
+    if let Some(nack) = reject_if_muted(ctx, user, &client_msg_id) {
+        return Some(nack_to_error(nack));
+    }
+
+    let is_coach = ctx.registry.is_coach(ctx.session_id, user.sub);
+    if let Some(nack) = reject_if_restricted(ctx, user, is_coach, &client_msg_id) {
+        return Some(nack_to_error(nack));
+    }
+
+    if let Err(retry_after) = ctx.manager.check_action_rate(ctx.session_id, user.sub) {
+        return Some(nack_to_error(ServerMessage::rate_limited(retry_after, client_msg_id)));
+    }
+
+    let content = match ctx.limits.sanitize(&content) {
+        Ok(content) => content,
+        Err(e) => return Some(nack_to_error(content_error(e, client_msg_id))),
+    };
+
+    let (content, flags) = match ctx.filters.apply(content) {
+        FilterVerdict::Deliver { content, flags } => (content, flags),
+        FilterVerdict::Reject { filter, reason } => return Some(nack_to_error(filter_rejection(ctx, user, &filter, reason, client_msg_id))),
+    };
 
+    if let Err(e) = ctx.manager.authorize_message_change(ctx.session_id, message_id, user.sub, is_coach) {
+        return Some(message_change_error(e, client_msg_id));
+    }
+
+    let edited_at = Utc::now();
+    if let Err(e) = ctx.publisher.publish_message_edited(ctx.session_id, message_id, user.sub, &content.content, &content.links, edited_at).await {
+        eprintln!("❌ Dropping edit of message {} after publish failure: {}", message_id, e);
+        return Some(ServerMessage::error(ErrorCode::PublishFailed, "Edit could not be saved, please retry", client_msg_id));
+    }
+
+    let editor = SenderInfo { id: user.sub, name: user.name.clone() };
+    publish_flags(ctx, message_id, &editor, &content.content, false, &flags).await;
+
+    ctx.manager.apply_edit(ctx.session_id, message_id, content, user.sub, edited_at);
+    return None;
+}
+
+/// Deletes a buffered message once the deletion is published. Returns the
+/// error frame for the requester, if any.
+async fn handle_delete(
+    ctx: &ConnectionContext,
+    user: &Claims,
+    message_id: Uuid,
+    client_msg_id: Option<String>
+) -> Option<ServerMessage> {
+    if let Err(retry_after) = ctx.manager.check_action_rate(ctx.session_id, user.sub) {
+        return Some(nack_to_error(ServerMessage::rate_limited(retry_after, client_msg_id)));
+    }
+
+    let is_coach = ctx.registry.is_coach(ctx.session_id, user.sub);
+    if let Err(e) = ctx.manager.authorize_message_change(ctx.session_id, message_id, user.sub, is_coach) {
+        return Some(message_change_error(e, client_msg_id));
+    }
+
+    let deleted_at = Utc::now();
+    if let Err(e) = ctx.publisher.publish_message_deleted(ctx.session_id, message_id, user.sub, deleted_at).await {
+        eprintln!("❌ Dropping deletion of message {} after publish failure: {}", message_id, e);
+        return Some(ServerMessage::error(ErrorCode::PublishFailed, "Deletion could not be saved, please retry", client_msg_id));
+    }
+
+    ctx.manager.apply_delete(ctx.session_id, message_id, user.sub, deleted_at);
+    return None;
+}
+
+fn handle_reaction(
+    ctx: &ConnectionContext,
+    user: &Claims,
+    message_id: Uuid,
+    emoji: &str,
+    added: bool,
+    client_msg_id: Option<String>
+) -> Option<ServerMessage> {
+    if let Err(retry_after) = ctx.manager.check_action_rate(ctx.session_id, user.sub) {
+        return Some(nack_to_error(ServerMessage::rate_limited(retry_after, client_msg_id)));
+    }
+
+    return ctx.manager.react(ctx.session_id, message_id, user.sub, emoji, added).err().map(|e| match e {
+        ReactionError::MessageNotFound => ServerMessage::error(ErrorCode::MessageNotFound, "Message not found", client_msg_id),
+        ReactionError::InvalidEmoji => ServerMessage::error(ErrorCode::InvalidReaction, "Reaction must be a single emoji", client_msg_id),
+        ReactionError::LimitReached => ServerMessage::error(
+            ErrorCode::ReactionLimitReached,
+            "This message cannot take any more distinct reactions",
+            client_msg_id
+        ),
+    });
+}
+
+/// Coach only: switches the session's chat mode. Returns the error frame
+/// for the requester, if any.
+fn handle_set_chat_mode(ctx: &ConnectionContext, user: &Claims, mode: ChatMode, client_msg_id: Option<String>) -> Option<ServerMessage> {
+    if !ctx.registry.is_coach(ctx.session_id, user.sub) {
+        return Some(ServerMessage::error(ErrorCode::Forbidden, "Only the session's coach can change the chat mode", client_msg_id));
+    }
+
+    let settings = ctx.registry.set_chat_mode(ctx.session_id, mode);
+    broadcast_chat_settings(ctx, &settings);
+    return None;
+}
+
+/// Coach only: sets slow mode, or lifts it for a zero interval. Returns the
+/// error frame for the requester, if any.
+fn handle_set_slow_mode(ctx: &ConnectionContext, user: &Claims, interval_secs: u64, client_msg_id: Option<String>) -> Option<ServerMessage> {
+    if !ctx.registry.is_coach(ctx.session_id, user.sub) {
+        return Some(ServerMessage::error(ErrorCode::Forbidden, "Only the session's coach can change slow mode", client_msg_id));
+    }
+
+    let settings = ctx.registry.set_slow_mode(ctx.session_id, Some(Duration::from_secs(interval_secs)));
+    broadcast_chat_settings(ctx, &settings);
+    return None;
+}
+
+/// Coach only: grants or revokes co-host rights. Returns the error frame for
+/// the requester, if any.
+fn handle_set_co_host(
+    ctx: &ConnectionContext,
+    user: &Claims,
+    user_id: Uuid,
+    co_host: bool,
+    client_msg_id: Option<String>
+) -> Option<ServerMessage> {
+    if !ctx.registry.is_coach(ctx.session_id, user.sub) {
+        return Some(ServerMessage::error(ErrorCode::Forbidden, "Only the session's coach can designate co-hosts", client_msg_id));
+    }
+
+    if let Some(settings) = ctx.registry.set_co_host(ctx.session_id, user_id, co_host) {
+        broadcast_chat_settings(ctx, &settings);
+    }
+    return None;
+}
+
+/// Applies a coach's moderation action and announces it to the session.
+/// Returns the error frame for the requester, if any.
+fn handle_moderation(
+    ctx: &ConnectionContext,
+    moderator: &Claims,
+    action: ModerationAction,
+    user_id: Uuid,
+    duration: Option<Duration>,
+    client_msg_id: Option<String>
+) -> Option<ServerMessage> {
+    if !ctx.registry.is_coach(ctx.session_id, moderator.sub) {
+        return Some(ServerMessage::error(ErrorCode::Forbidden, "Only the session's coach can moderate", client_msg_id));
+    }
+
+    if user_id == moderator.sub {
+        return Some(ServerMessage::error(ErrorCode::Forbidden, "The coach cannot moderate themselves", client_msg_id));
+    }
+
+    let mut until = None;
+    match action {
+        ModerationAction::Mute => {
+            let duration = duration.unwrap_or_default().min(MAX_MUTE_DURATION);
+            if duration.is_zero() {
+                return Some(ServerMessage::error(ErrorCode::InvalidMessage, "Mute duration must be positive", client_msg_id));
+            }
+
+            let muted_until = Utc::now() + chrono::Duration::from_std(duration).unwrap_or_default();
+            ctx.registry.mute(ctx.session_id, user_id, muted_until);
+            until = Some(muted_until);
+        }
+        ModerationAction::Unmute => ctx.registry.unmute(ctx.session_id, user_id),
+        ModerationAction::Kick => {
+            ctx.manager.disconnect_user(ctx.session_id, user_id, CloseCode::Kicked);
+        }
+        ModerationAction::Ban => {
+            ctx.registry.ban(ctx.session_id, user_id);
+            ctx.manager.disconnect_user(ctx.session_id, user_id, CloseCode::Kicked);
+        }
+        ModerationAction::Unban => ctx.registry.unban(ctx.session_id, user_id),
+    }
+
+    let announcement = ServerMessage::Moderation { action, user_id, moderator_id: moderator.sub, until };
+    ctx.manager.broadcast_message(ctx.session_id, &announcement.to_json(), None);
+    return None;
+}
+
+/// Publishes a `moderation.report` with a snapshot of the buffered
+/// conversation, returning the `ack` (carrying the report id) or `nack`.
+async fn handle_report(
+    ctx: &ConnectionContext,
+    user: &Claims,
+    message_id: Option<Uuid>,
+    user_id: Option<Uuid>,
+    reason: &str,
+    client_msg_id: Option<String>
+) -> ServerMessage {
+    let invalid = |message: &str, client_msg_id| ServerMessage::nack(ErrorCode::InvalidReport, message, client_msg_id);
+
+    let reason_limits = ContentLimits { max_content_chars: MAX_REPORT_REASON_CHARS, ..ContentLimits::clone(&ctx.limits) };
+    let reason = match reason_limits.validate_content(&strip_html(reason)) {
+        Ok(reason) => reason,
+        Err(ContentError::ControlCharacter) => return invalid("Report reasons cannot contain control characters", client_msg_id),
+        Err(_) => return invalid(&format!("A report needs a reason of at most {} characters", MAX_REPORT_REASON_CHARS), client_msg_id),
+    };
+
+    if message_id.is_none() && user_id.is_none() {
+        return invalid("A report must name a message_id or a user_id", client_msg_id);
+    }
+
+    if let Some(user_id) = user_id && !is_session_member(ctx, user_id) {
+        return invalid("The reported user is not a member of this session", client_msg_id);
+    }
+
+    if let Err(retry_after) = ctx.manager.check_report_rate(ctx.session_id, user.sub) {
+        return ServerMessage::rate_limited(retry_after, client_msg_id);
+    }
+
+    let Some(report) = ctx.manager.report_context(ctx.session_id, user.sub, message_id, user_id) else {
+        return ServerMessage::nack(ErrorCode::MessageNotFound, "Message not found", client_msg_id);
+    };
+
+    if user_id.is_some_and(|user_id| user_id != report.reported_user_id) {
+        return invalid("The reported message was not sent by that user", client_msg_id);
+    }
+
+    if report.reported_user_id == user.sub {
+        return invalid("You cannot report yourself", client_msg_id);
+    }
+
+    let report_id = Uuid::new_v4();
+    let reported_at = Utc::now();
+    let reporter = SenderInfo { id: user.sub, name: user.name.clone() };
+    if let Err(e) = ctx.publisher.publish_report(ctx.session_id, report_id, &reporter, &reason, &report, reported_at).await {
+        eprintln!("❌ Dropping report from user {} after publish failure: {}", user.sub, e);
+        return ServerMessage::nack(ErrorCode::PublishFailed, "Report could not be sent, please retry", client_msg_id);
+    }
+
+    println!("🚩 User {} reported user {} in session {}", user.sub, report.reported_user_id, ctx.session_id);
+    return ServerMessage::Ack { client_msg_id, message_id: report_id, seq: None, sent_at: reported_at };
+}
+
+/// Whether `user_id` may connect to `session_id`: the coach or a joined
+/// participant according to NATS events, or, until `session.created` has been
+/// seen, according to auth-service. Positive lookups are cached per user,
+/// together with the coach they report. Lookup failures deny access.
+async fn is_allowed_to_connect(
+    registry: &SessionRegistry,
+    lookup: &MembershipLookup,
+    session_id: Uuid,
+    user_id: Uuid
+) -> bool {
+    if registry.is_member(session_id, user_id) {
+        return true;
+    }
+
+    if registry.is_known(session_id) || !lookup.is_enabled() {
+        return false;
+    }
+
+    return match lookup.lookup(session_id, user_id).await {
+        Ok(Some(membership)) => {
+            registry.record_coach(session_id, membership.coach_id);
+            if membership.coach_id != user_id {
+                registry.record_joined(session_id, user_id);
+            }
+            true
+        }
+        Ok(None) => false,
+        Err(e) => {
+            eprintln!("❌ Membership lookup failed for user {} in session {}: {}", user_id, session_id, e);
+            false
+        }
+    };
+}
+
+fn token_lifetime(exp: usize) -> Duration {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    return Duration::from_secs((exp as u64).saturating_sub(now));
+}
+
+#[allow(clippy::too_many_arguments)] // one argument per actix extractor
 pub async fn ws_route(
     req: HttpRequest,
     stream: web::Payload,
-    session_id: web::Path<uuid::Uuid>,
+    session_id: web::Path<Uuid>,
     query: web::Query<WsConnectQuery>,
     manager: web::Data<SessionManager>,
-    publisher: web::Data<NatsPublisher>
+    publisher: web::Data<NatsPublisher>,
+    registry: web::Data<SessionRegistry>,
+    lookup: web::Data<MembershipLookup>,
+    limits: web::Data<ContentLimits>,
+    filters: web::Data<FilterPipeline>
 ) -> Result<HttpResponse, Error> {
     let claims = match jwt::validate_token(&query.token) {
         Ok(claims) => claims,
@@ -35,52 +719,258 @@ pub async fn ws_route(
         }
     };
 
-    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
     let session_id = session_id.into_inner();
+    if registry.is_ended(session_id) {
+        println!("🏁 Refusing user {} in ended session {}", claims.sub, session_id);
+        return Ok(HttpResponse::Gone().json(SessionUnavailableResponse {
+            code: ErrorCode::SessionEnded,
+            message: "Session has ended",
+            start_at: None,
+        }));
+    }
+
+    if !is_allowed_to_connect(&registry, &lookup, session_id, claims.sub).await {
+        println!("🚫 Refusing user {} who is not a member of session {}", claims.sub, session_id);
+        return Ok(HttpResponse::Forbidden().body("Not a member of this session"));
+    }
+
+    if registry.is_banned(session_id, claims.sub) {
+        println!("⛔ Refusing banned user {} in session {}", claims.sub, session_id);
+        return Ok(HttpResponse::Forbidden().body("Banned from this session"));
+    }
+
+    if let Some(start_at) = registry.start_pending(session_id, Utc::now()) {
+        println!("⏰ Refusing user {} before session {} opens (starts {})", claims.sub, session_id, start_at);
+        return Ok(HttpResponse::Forbidden().json(SessionUnavailableResponse {
+            code: ErrorCode::SessionNotStarted,
+            message: "Session has not started yet",
+            start_at: Some(start_at),
+        }));
+    }
+
+    let (response, mut session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let mut msg_stream = msg_stream.max_frame_size(limits.max_frame_bytes);
     let conn_id = rand::random::<usize>();
     let (tx, mut rx) = mpsc::channel::<String>(16);
+    let (close_tx, mut close_rx) = oneshot::channel::<CloseCode>();
 
-    manager.insert(session_id, conn_id, Connection { sender: tx, user_info: claims.clone() });
+    let connection = Connection { sender: tx, user_info: claims.clone(), closer: Some(close_tx) };
+    let replay = manager.insert(session_id, conn_id, connection, query.last_seq);
+    let chat_settings = registry.chat_settings(session_id);
+    let ctx = ConnectionContext { session_id, conn_id, manager: manager.clone(), publisher, registry, limits, filters };
     
     actix_web::rt::spawn(async move {
         let mut interval = interval(HEARTBEAT_INTERVAL);
+        let mut protocol_errors = 0;
+        let mut rate_violations = 0;
+        let mut closer_dropped = false;
+        let token_expiry = sleep(token_lifetime(claims.exp));
+        tokio::pin!(token_expiry);
+
+        if !chat_settings.is_default()
+            && session.text(chat_settings.to_message().to_json()).await.is_err() {
+            eprintln!("❌ Failed to send chat settings to conn_id={}", conn_id);
+            manager.remove(session_id, conn_id);
+            return;
+        }
+
+        let replay_frame = match replay {
+            Replay::Messages(messages) if messages.is_empty() => None,
+            Replay::Messages(messages) => {
+                println!("📜 Replaying {} messages to conn_id={}", messages.len(), conn_id);
+                Some(ServerMessage::History { messages })
+            }
+            Replay::ResyncRequired { latest_seq } => {
+                println!("🔁 Resync required for conn_id={} (latest seq {})", conn_id, latest_seq);
+                Some(ServerMessage::ResyncRequired { latest_seq })
+            }
+        };
+
+        if let Some(frame) = replay_frame
+            && session.text(frame.to_json()).await.is_err() {
+            eprintln!("❌ Failed to replay history to conn_id={}", conn_id);
+            manager.remove(session_id, conn_id);
+            return;
+        }
 
         loop {
             tokio::select! {
-                Some(Ok(msg)) = msg_stream.next() => {
-                    println!("📨 Received message from conn_id={}: {:?}", conn_id, msg);
-                    
+                Some(frame) = msg_stream.next() => {
+                    let msg = match frame {
+                        Ok(msg) => msg,
+                        Err(ProtocolError::Overflow) => {
+                            eprintln!("❌ Oversized frame from conn_id={}", conn_id);
+                            let message = format!("Frames cannot exceed {} bytes", ctx.limits.max_frame_bytes);
+                            let _ = send_error(&mut session, ErrorCode::FrameTooLarge, &message, None).await;
+                            let reason = CloseReason { code: actix_ws::CloseCode::Size, description: Some(message) };
+                            let _ = session.close(Some(reason)).await;
+                            break;
+                        }
+                        Err(e) => {
+                            eprintln!("❌ WebSocket protocol error from conn_id={}: {}", conn_id, e);
+                            close_with(session, CloseCode::ProtocolViolation).await;
+                            break;
+                        }
+                    };
+
                     match msg {
                         Message::Text(text) => {
-                            println!("📝 Text message received: {}", text);
-                            
-                            match serde_json::from_str::<ChatMessage>(&text.to_string()) {
-                                Ok(chat_msg) => {
-                                    println!("✅ Parsed ChatMessage: {:?}", chat_msg);
-                                    if let Some(sender_info) = manager.get_user_info(session_id, conn_id) {
-                                        publisher.publish_chat_message(session_id, &sender_info, &chat_msg.content).await;
-
-                                        let broadcast_msg = BroadcastMessage {
-                                            r#type: "chat_message".to_string(),
-                                            sender: SenderInfo {
-                                                id: sender_info.sub,
-                                                name: sender_info.name,
-                                            },
-                                            content: chat_msg.content,
-                                        };
-                                        
-                                        let broadcast_payload = serde_json::to_string(&broadcast_msg)
-                                            .unwrap_or_else(|_| "{}".to_string());
-                                        
-                                        println!("📡 Broadcasting message: {}", broadcast_payload);
-                                        manager.broadcast_message(session_id, &broadcast_payload, Some(conn_id));
-                                    } else {
-                                        eprintln!("❌ Could not find sender info for conn_id={}", conn_id);
+                            // Frame bodies are never logged: they carry direct messages and report reasons.
+                            println!("📝 Text frame of {} bytes received from conn_id={}", text.len(), conn_id);
+
+                            match ClientEnvelope::parse(&text) {
+                                Ok(envelope) => {
+                                    protocol_errors = 0;
+                                    let client_msg_id = envelope.client_msg_id;
+
+                                    if envelope.v > PROTOCOL_VERSION {
+                                        eprintln!("❌ Unsupported protocol version {} from conn_id={}", envelope.v, conn_id);
+                                        let message = format!("Protocol version {} is not supported, use {}", envelope.v, PROTOCOL_VERSION);
+                                        if send_error(&mut session, ErrorCode::UnsupportedVersion, &message, client_msg_id).await.is_err() {
+                                            break;
+                                        }
+                                        continue;
+                                    }
+
+                                    match envelope.message {
+                                        ClientMessage::Chat(chat_msg) => {
+                                            println!("✅ Parsed ChatMessage: {:?}", chat_msg);
+                                            let reply = handle_chat(&ctx, &claims, chat_msg, client_msg_id).await;
+                                            if send_reply(&mut session, Some(&reply)).await.is_err() {
+                                                eprintln!("❌ Failed to send reply to conn_id={}", conn_id);
+                                                break;
+                                            }
+
+                                            rate_violations = if is_rate_limited(&reply) { rate_violations + 1 } else { 0 };
+                                            if rate_violations >= MAX_RATE_VIOLATIONS {
+                                                close_with(session, CloseCode::RateLimited).await;
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::Ping {} => {
+                                            if session.text(ServerMessage::Pong {}.to_json()).await.is_err() {
+                                                eprintln!("❌ Failed to send pong to conn_id={}", conn_id);
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::TypingStart {} => {
+                                            manager.set_typing(session_id, &claims, true);
+                                        },
+                                        ClientMessage::TypingStop {} => {
+                                            manager.set_typing(session_id, &claims, false);
+                                        },
+                                        ClientMessage::ReadUpTo { seq } => {
+                                            let reply = handle_read_up_to(&ctx, &claims, seq, client_msg_id).await;
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::EditMessage { message_id, content } => {
+                                            let reply = handle_edit(&ctx, &claims, message_id, content, client_msg_id).await;
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::DeleteMessage { message_id } => {
+                                            let reply = handle_delete(&ctx, &claims, message_id, client_msg_id).await;
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::DirectMessage { to_user_id, content } => {
+                                            let reply = handle_direct_message(&ctx, &claims, to_user_id, content, client_msg_id).await;
+                                            if send_reply(&mut session, Some(&reply)).await.is_err() {
+                                                break;
+                                            }
+
+                                            rate_violations = if is_rate_limited(&reply) { rate_violations + 1 } else { 0 };
+                                            if rate_violations >= MAX_RATE_VIOLATIONS {
+                                                close_with(session, CloseCode::RateLimited).await;
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::React { message_id, emoji } => {
+                                            let reply = handle_reaction(&ctx, &claims, message_id, &emoji, true, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::Unreact { message_id, emoji } => {
+                                            let reply = handle_reaction(&ctx, &claims, message_id, &emoji, false, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::SetChatMode { mode } => {
+                                            let reply = handle_set_chat_mode(&ctx, &claims, mode, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::SetCoHost { user_id, co_host } => {
+                                            let reply = handle_set_co_host(&ctx, &claims, user_id, co_host, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::SetSlowMode { interval_secs } => {
+                                            let reply = handle_set_slow_mode(&ctx, &claims, interval_secs, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::MuteUser { user_id, duration_secs } => {
+                                            let duration = Some(Duration::from_secs(duration_secs));
+                                            let reply = handle_moderation(&ctx, &claims, ModerationAction::Mute, user_id, duration, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::UnmuteUser { user_id } => {
+                                            let reply = handle_moderation(&ctx, &claims, ModerationAction::Unmute, user_id, None, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::KickUser { user_id } => {
+                                            let reply = handle_moderation(&ctx, &claims, ModerationAction::Kick, user_id, None, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::BanUser { user_id } => {
+                                            let reply = handle_moderation(&ctx, &claims, ModerationAction::Ban, user_id, None, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::UnbanUser { user_id } => {
+                                            let reply = handle_moderation(&ctx, &claims, ModerationAction::Unban, user_id, None, client_msg_id);
+                                            if send_reply(&mut session, reply.as_ref()).await.is_err() {
+                                                break;
+                                            }
+                                        },
+                                        ClientMessage::Report { message_id, user_id, reason } => {
+                                            let reply = handle_report(&ctx, &claims, message_id, user_id, &reason, client_msg_id).await;
+                                            if send_reply(&mut session, Some(&reply)).await.is_err() {
+                                                break;
+                                            }
+                                        }
                                     }
                                 },
                                 Err(e) => {
-                                    eprintln!("❌ Failed to parse ChatMessage: {:?}", e);
-                                    eprintln!("   Raw message was: {}", text);
+                                    eprintln!("❌ Failed to parse client message from conn_id={}: {:?}", conn_id, e);
+
+                                    protocol_errors += 1;
+                                    if protocol_errors >= MAX_PROTOCOL_ERRORS {
+                                        close_with(session, CloseCode::ProtocolViolation).await;
+                                        break;
+                                    }
+
+                                    let client_msg_id = ClientEnvelope::extract_client_msg_id(&text);
+                                    if send_error(&mut session, ErrorCode::InvalidMessage, &e.to_string(), client_msg_id).await.is_err() {
+                                        break;
+                                    }
                                 }
                             }
                         },
@@ -92,6 +982,19 @@ pub async fn ws_route(
                             } 
                         },
                         Message::Pong(_) => {},
+                        Message::Binary(_) => {
+                            eprintln!("❌ Binary frame rejected from conn_id={}", conn_id);
+
+                            protocol_errors += 1;
+                            if protocol_errors >= MAX_PROTOCOL_ERRORS {
+                                close_with(session, CloseCode::ProtocolViolation).await;
+                                break;
+                            }
+
+                            if send_error(&mut session, ErrorCode::UnsupportedFrame, "Binary frames are not supported", None).await.is_err() {
+                                break;
+                            }
+                        },
                         Message::Close(reason) => {
                             println!("👋 Close message received from conn_id={}: {:?}", conn_id, reason);
                             break;
@@ -103,7 +1006,7 @@ pub async fn ws_route(
                 }
 
                 Some(msg_to_send) = rx.recv() => {
-                    println!("📤 Sending message to conn_id={}: {}", conn_id, msg_to_send);
+                    println!("📤 Sending {} bytes to conn_id={}", msg_to_send.len(), conn_id);
                     if session.text(msg_to_send).await.is_err() {
                         eprintln!("❌ Failed to send message to conn_id={}", conn_id);
                         break;
@@ -112,6 +1015,30 @@ pub async fn ws_route(
                     }
                 }
 
+                close = &mut close_rx, if !closer_dropped => {
+                    match close {
+                        Ok(code) => {
+                            println!("🚪 Server closing conn_id={} ({:?})", conn_id, code);
+                            // Flush what was queued before the close, such as the final system message.
+                            while let Ok(msg_to_send) = rx.try_recv() {
+                                if session.text(msg_to_send).await.is_err() {
+                                    break;
+                                }
+                            }
+                            close_with(session, code).await;
+                            break;
+                        }
+                        // The connection was dropped from the session without a close request.
+                        Err(_) => closer_dropped = true,
+                    }
+                }
+
+                _ = &mut token_expiry => {
+                    println!("⌛ Token expired for conn_id={}", conn_id);
+                    close_with(session, CloseCode::TokenExpired).await;
+                    break;
+                }
+
                 _ = interval.tick() => {
                     if session.ping(b"").await.is_err() {
                         eprintln!("❌ Failed to send heartbeat to conn_id={}", conn_id);
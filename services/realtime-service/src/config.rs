@@ -0,0 +1,19 @@
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Reads `key` from the environment, falling back to `default` when it is
+/// unset or cannot be parsed.
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    return match env::var(key) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid value '{}' for {}, using default", value, key);
+            default
+        }),
+        Err(_) => default,
+    };
+}
+
+pub fn env_secs(key: &str, default_secs: u64) -> Duration {
+    return Duration::from_secs(env_or(key, default_secs));
+}
\ No newline at end of file
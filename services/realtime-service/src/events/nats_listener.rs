@@ -1,25 +1,54 @@
 use actix_web::web;
 use async_nats::Client;
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use serde::Deserialize;
 use std::env;
 use uuid::Uuid;
 
+use crate::model::protocol::{ChatMode, ServerMessage, SessionEndReason};
 use crate::services::session_manager::SessionManager;
+use crate::services::session_registry::SessionRegistry;
 
 #[derive(Debug, Deserialize)]
 struct EventPayload {
     event_type: String,
-    session_id: Uuid
+    session_id: Uuid,
+    user_id: Option<Uuid>,
+    coach_id: Option<Uuid>,
+    start_at: Option<DateTime<Utc>>,
+    mode: Option<ChatMode>
 }
 
-pub async fn run_nats_listener(manager: web::Data<SessionManager>) {
+impl EventPayload {
+    fn to_server_message(&self) -> Option<ServerMessage> {
+        return match (self.event_type.as_str(), self.user_id) {
+            ("session.created", _) => Some(ServerMessage::SessionCreated { session_id: self.session_id }),
+            ("session.started", _) => Some(ServerMessage::SessionStarted { session_id: self.session_id }),
+            ("session.ended", _) => Some(ServerMessage::SessionEnded {
+                session_id: self.session_id,
+                reason: SessionEndReason::Ended
+            }),
+            ("session.cancelled", _) => Some(ServerMessage::SessionEnded {
+                session_id: self.session_id,
+                reason: SessionEndReason::Cancelled
+            }),
+            ("session.joined", Some(user_id)) => Some(ServerMessage::SessionJoined {
+                session_id: self.session_id,
+                user_id
+            }),
+            _ => None
+        };
+    }
+}
+
+pub async fn run_nats_listener(manager: web::Data<SessionManager>, registry: web::Data<SessionRegistry>) {
     let nats_url = env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".to_string());
 
     match async_nats::connect(&nats_url).await {
         Ok(client) => {
             println!("Connected to NATS in {}", nats_url);
-            subscribe_to_subject(client, manager).await;
+            subscribe_to_subject(client, manager, registry).await;
         }
         Err(e) => {
             println!("Failed to connect to NATS: {}", e);
@@ -27,14 +56,22 @@ pub async fn run_nats_listener(manager: web::Data<SessionManager>) {
     }
 }
 
-async fn subscribe_to_subject(client: Client, manager: web::Data<SessionManager>) {
-    let subjects = vec!["session.created", "session.joined"];
+async fn subscribe_to_subject(client: Client, manager: web::Data<SessionManager>, registry: web::Data<SessionRegistry>) {
+    let subjects = vec![
+        "session.created",
+        "session.joined",
+        "session.started",
+        "session.ended",
+        "session.cancelled",
+        "session.chat_mode"
+    ];
 
     for subject in subjects {
         match client.subscribe(subject.to_string()).await {
             Ok(mut sub) => {
                 println!("Subscribed to subject: {}", subject);
                 let manager_clone = manager.clone();
+                let registry_clone = registry.clone();
 
                 tokio::spawn(async move {
                     while let Some(msg) = sub.next().await {
@@ -45,12 +82,49 @@ async fn subscribe_to_subject(client: Client, manager: web::Data<SessionManager>
                                     event.event_type, event.session_id
                                 );
 
-                                let broadcast_msg = serde_json::json!({
-                                    "type": event.event_type,
-                                    "sessionId": event.session_id
-                                }).to_string();
+                                if event.event_type == "session.created"
+                                    && let Some(coach_id) = event.coach_id {
+                                    registry_clone.record_created(event.session_id, coach_id);
+                                }
+
+                                if event.event_type == "session.created"
+                                    && let Some(start_at) = event.start_at {
+                                    registry_clone.record_schedule(event.session_id, start_at);
+                                }
+
+                                if event.event_type == "session.joined"
+                                    && let Some(user_id) = event.user_id {
+                                    registry_clone.record_joined(event.session_id, user_id);
+                                }
+
+                                if event.event_type == "session.started" {
+                                    registry_clone.record_started(event.session_id);
+                                }
+
+                                // Stored even without live connections; whoever connects next gets it.
+                                if event.event_type == "session.chat_mode" {
+                                    match event.mode {
+                                        Some(mode) => {
+                                            let settings = registry_clone.set_chat_mode(event.session_id, mode);
+                                            manager_clone.broadcast_message(event.session_id, &settings.to_message().to_json(), None);
+                                        }
+                                        None => println!("Ignoring chat mode event without mode: {:?}", event),
+                                    }
+                                    continue;
+                                }
 
-                                manager_clone.broadcast_message(event.session_id, &broadcast_msg, None);
+                                match event.to_server_message() {
+                                    Some(final_msg @ ServerMessage::SessionEnded { .. }) => {
+                                        registry_clone.record_ended(event.session_id);
+                                        manager_clone.end_session(event.session_id, &final_msg.to_json());
+                                    }
+                                    Some(broadcast_msg) => {
+                                        manager_clone.broadcast_message(event.session_id, &broadcast_msg.to_json(), None);
+                                    }
+                                    None => {
+                                        println!("Ignoring unsupported event: {:?}", event);
+                                    }
+                                }
                             }
                             Err(e) => {
                                 println!("Failed to parse event payload: {}", e);
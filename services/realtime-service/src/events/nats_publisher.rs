@@ -1,17 +1,122 @@
 use async_nats::{connect, Client, Error};
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 use serde_json::to_vec;
 use std::env;
 use uuid::Uuid;
-use crate::auth::jwt::Claims;
+use crate::model::chat_message::{BroadcastMessage, DirectMessage, LinkEntity, SenderInfo};
+use crate::services::message_filter::FilterFlag;
+use crate::services::session_manager::ReportContext;
 
 #[derive(Serialize)]
 struct ChatMessageReceivedEvent<'a> {
     event_type: &'static str,
+    message_id: Uuid,
+    seq: u64,
+    sent_at: DateTime<Utc>,
     session_id: Uuid,
     user_id: Uuid,
     user_name: &'a str,
-    content: &'a str
+    content: &'a str,
+    /// Parent message for threaded replies; `null` for top-level messages.
+    reply_to: Option<Uuid>,
+    mentions: &'a [Uuid],
+    links: &'a [LinkEntity]
+}
+
+#[derive(Serialize)]
+struct DirectMessageSentEvent<'a> {
+    event_type: &'static str,
+    message_id: Uuid,
+    sent_at: DateTime<Utc>,
+    session_id: Uuid,
+    user_id: Uuid,
+    user_name: &'a str,
+    to_user_id: Uuid,
+    content: &'a str,
+    links: &'a [LinkEntity],
+    /// Always true; lets persistence store the record as private without
+    /// relying on the subject name.
+    is_private: bool
+}
+
+#[derive(Serialize)]
+struct MessageFlaggedEvent<'a> {
+    event_type: &'static str,
+    message_id: Uuid,
+    session_id: Uuid,
+    /// The author of the flagged content; for edits, the editor.
+    user_id: Uuid,
+    user_name: &'a str,
+    /// The content as delivered, after masking.
+    content: &'a str,
+    is_private: bool,
+    flags: &'a [FilterFlag],
+    flagged_at: DateTime<Utc>
+}
+
+#[derive(Serialize)]
+struct ModerationReportEvent<'a> {
+    event_type: &'static str,
+    report_id: Uuid,
+    session_id: Uuid,
+    reporter_id: Uuid,
+    reporter_name: &'a str,
+    reported_user_id: Uuid,
+    message_id: Option<Uuid>,
+    reason: &'a str,
+    /// The reported message as it was buffered; `null` for user reports.
+    message: Option<&'a BroadcastMessage>,
+    /// The reported direct message, sent to the reporter; `null` otherwise.
+    direct_message: Option<&'a DirectMessage>,
+    context: &'a [BroadcastMessage],
+    reported_at: DateTime<Utc>
+}
+
+#[derive(Serialize)]
+struct ChatMentionEvent<'a> {
+    event_type: &'static str,
+    message_id: Uuid,
+    session_id: Uuid,
+    mentioned_user_id: Uuid,
+    sender_id: Uuid,
+    sender_name: &'a str,
+    content: &'a str,
+    sent_at: DateTime<Utc>,
+    /// Whether the mentioned user had a live connection when the message was
+    /// sent; notification-worker only pushes to users who did not.
+    is_connected: bool
+}
+
+#[derive(Serialize)]
+struct ChatMessageReadEvent {
+    event_type: &'static str,
+    session_id: Uuid,
+    user_id: Uuid,
+    /// Every message up to and including this sequence number was seen.
+    seq: u64,
+    read_at: DateTime<Utc>
+}
+
+#[derive(Serialize)]
+struct ChatMessageEditedEvent<'a> {
+    event_type: &'static str,
+    message_id: Uuid,
+    session_id: Uuid,
+    /// The user who made the edit, not necessarily the original sender.
+    user_id: Uuid,
+    content: &'a str,
+    links: &'a [LinkEntity],
+    edited_at: DateTime<Utc>
+}
+
+#[derive(Serialize)]
+struct ChatMessageDeletedEvent {
+    event_type: &'static str,
+    message_id: Uuid,
+    session_id: Uuid,
+    user_id: Uuid,
+    deleted_at: DateTime<Utc>
 }
 
 pub struct NatsPublisher {
@@ -26,27 +131,211 @@ impl NatsPublisher {
         Ok(NatsPublisher { client })
     }
 
-    pub async fn publish_chat_message(&self, session_id: Uuid, sender_info: &Claims, content: &str) {
+    pub async fn publish_chat_message(&self, session_id: Uuid, message: &BroadcastMessage) -> Result<(), Error> {
         let event = ChatMessageReceivedEvent {
             event_type: "chat.message.received",
+            message_id: message.id,
+            seq: message.seq,
+            sent_at: message.sent_at,
             session_id,
-            user_id: sender_info.sub,
-            user_name: &sender_info.name,
-            content
-        };
-
-        match to_vec(&event) {
-            Ok(payload) => {
-                let subject = format!("chat.message.received.{}", session_id);
-                if let Err(e) = self.client.publish(subject, payload.into()).await {
-                    eprintln!("Failed to publish chat message event: {}", e);
-                } else {
-                    println!("Published chat message event for session: {}", session_id);
-                }
-            }
-            Err(e) => {
-                eprintln!("Failed to serialize chat message event: {}", e);
-            }
+            user_id: message.sender.id,
+            user_name: &message.sender.name,
+            content: &message.content,
+            reply_to: message.reply_to,
+            mentions: &message.mentions,
+            links: &message.links
+        };
+
+        let subject = format!("chat.message.received.{}", session_id);
+        self.publish_event(subject, &event).await?;
+
+        println!("Published chat message event for session: {}", session_id);
+        return Ok(());
+    }
+
+    pub async fn publish_direct_message(&self, session_id: Uuid, message: &DirectMessage) -> Result<(), Error> {
+        let event = DirectMessageSentEvent {
+            event_type: "chat.direct.sent",
+            message_id: message.id,
+            sent_at: message.sent_at,
+            session_id,
+            user_id: message.sender.id,
+            user_name: &message.sender.name,
+            to_user_id: message.to_user_id,
+            content: &message.content,
+            links: &message.links,
+            is_private: true
+        };
+
+        let subject = format!("chat.direct.sent.{}", session_id);
+        self.publish_event(subject, &event).await?;
+
+        println!("Published direct message event for session: {}", session_id);
+        return Ok(());
+    }
+
+    /// Reports a delivered message that moderation filters flagged for review.
+    pub async fn publish_message_flagged(
+        &self,
+        session_id: Uuid,
+        message_id: Uuid,
+        author: &SenderInfo,
+        content: &str,
+        is_private: bool,
+        flags: &[FilterFlag]
+    ) -> Result<(), Error> {
+        let event = MessageFlaggedEvent {
+            event_type: "moderation.flagged",
+            message_id,
+            session_id,
+            user_id: author.id,
+            user_name: &author.name,
+            content,
+            is_private,
+            flags,
+            flagged_at: Utc::now()
+        };
+
+        let subject = format!("moderation.flagged.{}", session_id);
+        self.publish_event(subject, &event).await?;
+
+        println!("Published flag for message {} in session: {}", message_id, session_id);
+        return Ok(());
+    }
+
+    pub async fn publish_report(
+        &self,
+        session_id: Uuid,
+        report_id: Uuid,
+        reporter: &SenderInfo,
+        reason: &str,
+        report: &ReportContext,
+        reported_at: DateTime<Utc>
+    ) -> Result<(), Error> {
+        let event = ModerationReportEvent {
+            event_type: "moderation.report",
+            report_id,
+            session_id,
+            reporter_id: reporter.id,
+            reporter_name: &reporter.name,
+            reported_user_id: report.reported_user_id,
+            message_id: report.message.as_ref().map(|message| message.id)
+                .or(report.direct_message.as_ref().map(|message| message.id)),
+            reason,
+            message: report.message.as_ref(),
+            direct_message: report.direct_message.as_ref(),
+            context: &report.context,
+            reported_at
+        };
+
+        let subject = format!("moderation.report.{}", session_id);
+        self.publish_event(subject, &event).await?;
+
+        println!("Published report {} for session: {}", report_id, session_id);
+        return Ok(());
+    }
+
+    pub async fn publish_mention(
+        &self,
+        session_id: Uuid,
+        message: &BroadcastMessage,
+        mentioned_user_id: Uuid,
+        is_connected: bool
+    ) -> Result<(), Error> {
+        let event = ChatMentionEvent {
+            event_type: "chat.mention",
+            message_id: message.id,
+            session_id,
+            mentioned_user_id,
+            sender_id: message.sender.id,
+            sender_name: &message.sender.name,
+            content: &message.content,
+            sent_at: message.sent_at,
+            is_connected
+        };
+
+        let subject = format!("chat.mention.{}", session_id);
+        self.publish_event(subject, &event).await?;
+
+        println!("Published mention of user {} in session: {}", mentioned_user_id, session_id);
+        return Ok(());
+    }
+
+    pub async fn publish_message_edited(
+        &self,
+        session_id: Uuid,
+        message_id: Uuid,
+        user_id: Uuid,
+        content: &str,
+        links: &[LinkEntity],
+        edited_at: DateTime<Utc>
+    ) -> Result<(), Error> {
+        let event = ChatMessageEditedEvent {
+            event_type: "chat.message.edited",
+            message_id,
+            session_id,
+            user_id,
+            content,
+            links,
+            edited_at
+        };
+
+        let subject = format!("chat.message.edited.{}", session_id);
+        self.publish_event(subject, &event).await?;
+
+        println!("Published edit of message {} in session: {}", message_id, session_id);
+        return Ok(());
+    }
+
+    pub async fn publish_message_deleted(
+        &self,
+        session_id: Uuid,
+        message_id: Uuid,
+        user_id: Uuid,
+        deleted_at: DateTime<Utc>
+    ) -> Result<(), Error> {
+        let event = ChatMessageDeletedEvent {
+            event_type: "chat.message.deleted",
+            message_id,
+            session_id,
+            user_id,
+            deleted_at
+        };
+
+        let subject = format!("chat.message.deleted.{}", session_id);
+        self.publish_event(subject, &event).await?;
+
+        println!("Published deletion of message {} in session: {}", message_id, session_id);
+        return Ok(());
+    }
+
+    pub async fn publish_read_receipt(&self, session_id: Uuid, user_id: Uuid, seq: u64) -> Result<(), Error> {
+        let event = ChatMessageReadEvent {
+            event_type: "chat.message.read",
+            session_id,
+            user_id,
+            seq,
+            read_at: Utc::now()
+        };
+
+        let subject = format!("chat.message.read.{}", session_id);
+        self.publish_event(subject, &event).await?;
+
+        println!("Published read receipt for user {} in session: {}", user_id, session_id);
+        return Ok(());
+    }
+
+    async fn publish_event<T: Serialize>(&self, subject: String, event: &T) -> Result<(), Error> {
+        let payload = to_vec(event).map_err(|e| {
+            eprintln!("Failed to serialize event for '{}': {}", subject, e);
+            e
+        })?;
+
+        if let Err(e) = self.client.publish(subject.clone(), payload.into()).await {
+            eprintln!("Failed to publish event to '{}': {}", subject, e);
+            return Err(e.into());
         }
+
+        return Ok(());
     }
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+#![allow(clippy::needless_return)]
+
+pub mod api;
+pub mod auth;
+pub mod config;
+pub mod events;
+pub mod middleware;
+pub mod model;
+pub mod services;
\ No newline at end of file
@@ -1,20 +1,17 @@
-mod api;
-mod services;
-mod model;
-mod auth;
-mod events {
-    pub mod nats_listener;
-    pub mod nats_publisher;
-}
-mod middleware;
+#![allow(clippy::needless_return)]
 
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
 use actix_web::middleware::from_fn;
-use services::session_manager::SessionManager;
+use realtime_service::{api, events, services};
+use realtime_service::services::session_manager::SessionManager;
+use realtime_service::services::content_validation::ContentLimits;
+use realtime_service::services::membership::MembershipLookup;
+use realtime_service::services::message_filter::FilterPipeline;
+use realtime_service::services::session_registry::SessionRegistry;
 use std::env;
 use std::io::Result;
 
-use crate::middleware::metrics::{metrics_handler, metrics_middleware, register_metrics};
+use realtime_service::middleware::metrics::{metrics_handler, metrics_middleware, register_metrics};
 
 #[get("/health")]
 async fn health_check() -> impl Responder {
@@ -30,18 +27,29 @@ async fn main() -> Result<()> {
     register_metrics();
 
     let session_manager = web::Data::new(SessionManager::new());
+    let session_registry = web::Data::new(SessionRegistry::new());
+    let membership_lookup = web::Data::new(MembershipLookup::from_env());
+    let content_limits = web::Data::new(ContentLimits::from_env());
+    let message_filters = match FilterPipeline::from_env() {
+        Ok(filters) => web::Data::new(filters),
+        Err(e) => {
+            eprintln!("Failed to load message filters: {}", e);
+            return Err(e);
+        }
+    };
     let nats_publisher = match events::nats_publisher::NatsPublisher::new().await {
         Ok(publisher) => web::Data::new(publisher),
         Err(e) => {
             eprintln!("Failed to create NATS publisher: {}", e);
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
+            return Err(std::io::Error::other(
                 format!("Failed to create NATS publisher: {}", e),
             ));
         }
     };
 
-    tokio::spawn(events::nats_listener::run_nats_listener(session_manager.clone()));
+    tokio::spawn(events::nats_listener::run_nats_listener(session_manager.clone(), session_registry.clone()));
+    tokio::spawn(services::typing_expiry::run_typing_expiry(session_manager.clone()));
+    tokio::spawn(services::state_eviction::run_state_eviction(session_manager.clone(), session_registry.clone()));
 
     let port_str = env::var("APP_PORT").unwrap_or_else(|_| "8080".to_string());
     let port = port_str.parse::<u16>().unwrap();
@@ -52,6 +60,10 @@ async fn main() -> Result<()> {
         App::new()
             .wrap(from_fn(metrics_middleware))
             .app_data(session_manager.clone())
+            .app_data(session_registry.clone())
+            .app_data(membership_lookup.clone())
+            .app_data(content_limits.clone())
+            .app_data(message_filters.clone())
             .app_data(nats_publisher.clone())
             .service(health_check)
             .route("/v1/ws/{session_id}", web::get().to(api::ws_handler::ws_route))
@@ -1,19 +1,84 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ChatMessage {
-    pub content: String
+    pub content: String,
+    /// Id of the message this one answers, for threading Q&A.
+    #[serde(default)]
+    pub reply_to: Option<Uuid>,
+    /// Ids of the session members referenced with `@` in `content`.
+    #[serde(default)]
+    pub mentions: Vec<Uuid>,
+    /// Filled in by the server from `content`; never read from clients.
+    #[serde(skip)]
+    pub links: Vec<LinkEntity>
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BroadcastMessage {
-    pub r#type: String,
+    pub id: Uuid,
+    pub seq: u64,
+    pub sent_at: DateTime<Utc>,
+    pub sender: SenderInfo,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<Uuid>,
+    /// Preview of the `reply_to` parent while it is still buffered and not
+    /// deleted; older parents are referenced by id only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quoted: Option<QuotedMessage>,
+    /// Mentioned user ids, for clients to highlight.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mentions: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkEntity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<DateTime<Utc>>,
+    /// Set on tombstones; `content` is emptied when a message is deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<ReactionCount>,
+}
+
+/// A private message between two members of a session. Direct messages are
+/// not sequenced or kept in the replay buffer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectMessage {
+    pub id: Uuid,
+    pub sent_at: DateTime<Utc>,
     pub sender: SenderInfo,
+    pub to_user_id: Uuid,
     pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<LinkEntity>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuotedMessage {
+    pub sender: SenderInfo,
+    /// The start of the parent's content, cut at a character boundary.
+    pub excerpt: String,
+}
+
+/// A link found in `content`. `start` and `end` are offsets in UTF-16 code
+/// units, end exclusive; `url` is normalized and always `http` or `https`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkEntity {
+    pub url: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SenderInfo {
     pub id: Uuid,
     pub name: String,
@@ -1 +1,3 @@
-pub mod chat_message;
\ No newline at end of file
+pub mod chat_message;
+pub mod presence;
+pub mod protocol;
\ No newline at end of file
@@ -0,0 +1,17 @@
+use crate::auth::jwt::Claims;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A user present in a live session, regardless of how many devices they
+/// are connected from.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Participant {
+    pub user_id: Uuid,
+    pub name: String,
+}
+
+impl From<&Claims> for Participant {
+    fn from(claims: &Claims) -> Self {
+        Participant { user_id: claims.sub, name: claims.name.clone() }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,374 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::model::chat_message::{BroadcastMessage, ChatMessage, DirectMessage, LinkEntity};
+use crate::model::presence::Participant;
+use crate::services::session_manager::DeliveryReceipt;
+
+/// Version of the WebSocket protocol spoken by this server. Clients may omit
+/// `v`, in which case the current version is assumed.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+fn default_version() -> u8 {
+    PROTOCOL_VERSION
+}
+
+/// Inbound frame sent by a client: `{"v": 1, "type": "...", ...}`.
+#[derive(Deserialize, Debug)]
+pub struct ClientEnvelope {
+    #[serde(default = "default_version")]
+    pub v: u8,
+    /// Opaque id chosen by the client, echoed back in any frame that refers
+    /// to this message. For chat it doubles as the idempotency key: resending
+    /// the same id returns the original `ack` instead of a second broadcast.
+    #[serde(default)]
+    pub client_msg_id: Option<String>,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Chat(ChatMessage),
+    Ping {},
+    /// Sent repeatedly while the user types; the indicator expires on its
+    /// own if the updates stop.
+    TypingStart {},
+    TypingStop {},
+    /// Marks every message up to and including `seq` as read.
+    ReadUpTo { seq: u64 },
+    /// Replaces the content of one of the sender's own messages.
+    EditMessage { message_id: Uuid, content: String },
+    /// Turns a message into a tombstone.
+    DeleteMessage { message_id: Uuid },
+    /// Sends `content` privately to `to_user_id` within the session.
+    DirectMessage { to_user_id: Uuid, content: String },
+    React { message_id: Uuid, emoji: String },
+    Unreact { message_id: Uuid, emoji: String },
+    /// Coach only: switches who may post chat messages.
+    SetChatMode { mode: ChatMode },
+    /// Coach only: grants or revokes the right to post in announcement-only mode.
+    SetCoHost { user_id: Uuid, co_host: bool },
+    /// Coach only: minimum seconds between two posts of the same participant;
+    /// zero turns slow mode off.
+    SetSlowMode { interval_secs: u64 },
+    /// Coach only: stops `user_id` from posting for `duration_secs`.
+    MuteUser { user_id: Uuid, duration_secs: u64 },
+    UnmuteUser { user_id: Uuid },
+    /// Coach only: closes `user_id`'s connections; they may reconnect.
+    KickUser { user_id: Uuid },
+    /// Coach only: closes `user_id`'s connections and refuses new ones for
+    /// the rest of the session.
+    BanUser { user_id: Uuid },
+    UnbanUser { user_id: Uuid },
+    /// Reports a message, or a user when no message is named, to trust and
+    /// safety. Direct messages can be reported by their recipient. Acked with
+    /// the report id as `message_id`.
+    Report {
+        #[serde(default)]
+        message_id: Option<Uuid>,
+        #[serde(default)]
+        user_id: Option<Uuid>,
+        reason: String,
+    },
+}
+
+impl ClientEnvelope {
+    /// Parses an inbound text frame. Bare `{"content": "..."}` frames from
+    /// clients that predate the typed protocol are still accepted as chat;
+    /// a frame that names a `type` never falls back, so a malformed private
+    /// or unknown message is refused rather than posted publicly.
+    pub fn parse(text: &str) -> Result<Self, serde_json::Error> {
+        return match serde_json::from_str::<ClientEnvelope>(text) {
+            Ok(envelope) => Ok(envelope),
+            Err(e) if Self::has_type(text) => Err(e),
+            Err(e) => match serde_json::from_str::<ChatMessage>(text) {
+                Ok(chat_msg) => Ok(ClientEnvelope {
+                    v: PROTOCOL_VERSION,
+                    client_msg_id: None,
+                    message: ClientMessage::Chat(chat_msg),
+                }),
+                Err(_) => Err(e),
+            },
+        };
+    }
+
+    fn has_type(text: &str) -> bool {
+        return serde_json::from_str::<serde_json::Value>(text)
+            .is_ok_and(|value| value.get("type").is_some());
+    }
+
+    /// Best-effort lookup of `client_msg_id` in a frame that failed to parse,
+    /// so the resulting error can still be correlated by the client.
+    pub fn extract_client_msg_id(text: &str) -> Option<String> {
+        return serde_json::from_str::<serde_json::Value>(text)
+            .ok()?
+            .get("client_msg_id")?
+            .as_str()
+            .map(|id| id.to_string());
+    }
+}
+
+/// Outbound frame sent to clients, serialized as `{"v": 1, "type": "...", ...}`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    ChatMessage(BroadcastMessage),
+    /// Delivered only to the recipient and the sender's other connections.
+    DirectMessage(DirectMessage),
+    /// Recent chat messages, oldest first, sent once right after connecting.
+    History { messages: Vec<BroadcastMessage> },
+    /// The `last_seq` passed on reconnect is no longer buffered; reload the
+    /// conversation from the history API and continue from `latest_seq`.
+    ResyncRequired { latest_seq: u64 },
+    /// Everyone currently connected, sent once to each new connection.
+    Presence { participants: Vec<Participant> },
+    /// A user's first connection to the session arrived.
+    ParticipantJoined(Participant),
+    /// A user's last connection to the session went away.
+    ParticipantLeft(Participant),
+    Typing {
+        #[serde(flatten)]
+        participant: Participant,
+        is_typing: bool,
+    },
+    MessageEdited {
+        message_id: Uuid,
+        content: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        links: Vec<LinkEntity>,
+        edited_at: DateTime<Utc>,
+        edited_by: Uuid,
+    },
+    /// Tombstone for a deleted message; clients should drop its content.
+    MessageDeleted {
+        message_id: Uuid,
+        deleted_at: DateTime<Utc>,
+        deleted_by: Uuid,
+    },
+    /// Delta for one emoji on one message; `count` is the new total.
+    ReactionUpdated {
+        message_id: Uuid,
+        emoji: String,
+        user_id: Uuid,
+        added: bool,
+        count: usize,
+    },
+    /// Current chat mode and co-hosts; sent on change, and on connect when
+    /// they differ from the defaults.
+    ChatSettings {
+        mode: ChatMode,
+        co_hosts: Vec<Uuid>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        slow_mode_secs: Option<u64>,
+    },
+    /// A coach acted on `user_id`; `until` is set for mutes.
+    Moderation {
+        action: ModerationAction,
+        user_id: Uuid,
+        moderator_id: Uuid,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        until: Option<DateTime<Utc>>,
+    },
+    /// `participant` has read every message up to and including `seq`.
+    ReadReceipt {
+        #[serde(flatten)]
+        participant: Participant,
+        seq: u64,
+    },
+    Pong {},
+    /// The chat message identified by `client_msg_id` was accepted, published
+    /// and fanned out to the session.
+    Ack {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_msg_id: Option<String>,
+        message_id: Uuid,
+        /// Absent for direct messages, which sit outside the session sequence.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seq: Option<u64>,
+        sent_at: DateTime<Utc>,
+    },
+    /// The chat message identified by `client_msg_id` was not delivered.
+    Nack {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_msg_id: Option<String>,
+        code: ErrorCode,
+        message: String,
+        /// Set on `rate_limited` nacks: when the next post will be accepted.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after_ms: Option<u64>,
+    },
+    SessionCreated { session_id: Uuid },
+    SessionStarted { session_id: Uuid },
+    /// Final message before the server closes every connection of the session.
+    SessionEnded { session_id: Uuid, reason: SessionEndReason },
+    SessionJoined { session_id: Uuid, user_id: Uuid },
+    Error {
+        code: ErrorCode,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_msg_id: Option<String>,
+        /// Set on `rate_limited` errors: when the next post will be accepted.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_after_ms: Option<u64>,
+    },
+}
+
+/// Who may post chat messages in a session.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatMode {
+    #[default]
+    Open,
+    /// Only the coach and co-hosts may post.
+    AnnouncementOnly,
+    /// Nobody may post.
+    Disabled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionEndReason {
+    Ended,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    Mute,
+    Unmute,
+    Kick,
+    Ban,
+    Unban,
+}
+
+/// Machine-readable reason carried by an `error` frame.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The frame was not valid JSON or did not match any known message type.
+    InvalidMessage,
+    /// The frame declared a protocol version newer than the server speaks.
+    UnsupportedVersion,
+    /// Binary frames are not part of the protocol.
+    UnsupportedFrame,
+    /// The connection is no longer registered in its session.
+    NotInSession,
+    /// The message could not be handed to NATS and was not broadcast.
+    PublishFailed,
+    /// The referenced message is unknown or no longer buffered.
+    MessageNotFound,
+    /// The user is not allowed to perform this operation.
+    Forbidden,
+    /// The reaction is not exactly one emoji sequence.
+    InvalidReaction,
+    /// The message already carries the maximum number of distinct emoji.
+    ReactionLimitReached,
+    /// A mentioned user is not a member of the session, or the message
+    /// mentions too many users.
+    InvalidMention,
+    /// The direct message recipient is the sender or not a session member.
+    InvalidRecipient,
+    /// The user was muted by the coach and cannot post yet.
+    Muted,
+    /// The session does not admit connections yet.
+    SessionNotStarted,
+    /// The session ended or was cancelled.
+    SessionEnded,
+    /// The session's chat mode does not allow this user to post.
+    ChatRestricted,
+    /// Too many posts in a short time, or faster than slow mode allows.
+    RateLimited,
+    /// The WebSocket frame exceeded the maximum size; the connection is closed.
+    FrameTooLarge,
+    /// The content is empty or only whitespace.
+    EmptyContent,
+    /// The content exceeds the maximum number of characters.
+    ContentTooLong,
+    /// The content contains control characters.
+    InvalidCharacters,
+    /// The content links to a scheme such as `javascript:` or `data:`.
+    UnsafeLink,
+    /// A moderation filter refused the content.
+    ContentRejected,
+    /// The report names nothing reportable or has no usable reason.
+    InvalidReport,
+}
+
+/// Application close codes sent when the server ends a connection. They live
+/// in the 4000-4999 range RFC 6455 reserves for private use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseCode {
+    /// The client kept sending frames that could not be understood.
+    ProtocolViolation = 4000,
+    /// The JWT used to open the connection has expired; reconnect with a fresh one.
+    TokenExpired = 4001,
+    /// A moderator removed the user from the session.
+    Kicked = 4002,
+    /// The session is over and accepts no further connections.
+    SessionEnded = 4003,
+    /// The client kept posting after being rate limited.
+    RateLimited = 4004,
+}
+
+impl CloseCode {
+    pub fn code(self) -> u16 {
+        return self as u16;
+    }
+
+    pub fn description(self) -> &'static str {
+        return match self {
+            CloseCode::ProtocolViolation => "protocol violation",
+            CloseCode::TokenExpired => "token expired",
+            CloseCode::Kicked => "kicked from session",
+            CloseCode::SessionEnded => "session ended",
+            CloseCode::RateLimited => "rate limit exceeded",
+        };
+    }
+}
+
+#[derive(Serialize)]
+struct ServerEnvelope<'a> {
+    v: u8,
+    #[serde(flatten)]
+    message: &'a ServerMessage,
+}
+
+impl ServerMessage {
+    pub fn error(code: ErrorCode, message: impl Into<String>, client_msg_id: Option<String>) -> Self {
+        return ServerMessage::Error { code, message: message.into(), client_msg_id, retry_after_ms: None };
+    }
+
+    pub fn nack(code: ErrorCode, message: impl Into<String>, client_msg_id: Option<String>) -> Self {
+        return ServerMessage::Nack { client_msg_id, code, message: message.into(), retry_after_ms: None };
+    }
+
+    /// `nack` for a post refused by the rate limiter or slow mode.
+    pub fn rate_limited(retry_after: Duration, client_msg_id: Option<String>) -> Self {
+        return ServerMessage::Nack {
+            code: ErrorCode::RateLimited,
+            message: "You are sending messages too fast".to_string(),
+            client_msg_id,
+            // Round up so retrying exactly on time is never too early.
+            retry_after_ms: Some(u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX).saturating_add(1)),
+        };
+    }
+
+    pub fn ack(client_msg_id: Option<String>, receipt: &DeliveryReceipt) -> Self {
+        return ServerMessage::Ack {
+            client_msg_id,
+            message_id: receipt.message_id,
+            seq: receipt.seq,
+            sent_at: receipt.sent_at,
+        };
+    }
+
+    pub fn to_json(&self) -> String {
+        let envelope = ServerEnvelope { v: PROTOCOL_VERSION, message: self };
+        return serde_json::to_string(&envelope).unwrap_or_else(|_| "{}".to_string());
+    }
+}
@@ -0,0 +1,83 @@
+use crate::config::env_or;
+use crate::model::chat_message::LinkEntity;
+use crate::services::sanitizer::{extract_links, strip_html};
+use unicode_normalization::UnicodeNormalization;
+
+/// Bounds on what clients may send, read from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct ContentLimits {
+    /// Largest WebSocket frame accepted, in bytes (`MAX_FRAME_BYTES`).
+    pub max_frame_bytes: usize,
+    /// Longest message content accepted after normalization, in characters
+    /// (`MAX_CONTENT_CHARS`).
+    pub max_content_chars: usize
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContentError {
+    /// Nothing left after trimming whitespace.
+    Empty,
+    TooLong { max_chars: usize },
+    /// Control characters other than newlines and tabs.
+    ControlCharacter,
+    /// A link using a scheme such as `javascript:` or `data:`.
+    UnsafeLink { scheme: String }
+}
+
+/// Plain-text content ready to broadcast, with the links found in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SanitizedContent {
+    pub content: String,
+    pub links: Vec<LinkEntity>
+}
+
+impl Default for ContentLimits {
+    fn default() -> Self {
+        ContentLimits {
+            max_frame_bytes: 32 * 1024,
+            max_content_chars: 2000
+        }
+    }
+}
+
+impl ContentLimits {
+    pub fn from_env() -> Self {
+        let defaults = ContentLimits::default();
+
+        return ContentLimits {
+            max_frame_bytes: env_or("MAX_FRAME_BYTES", defaults.max_frame_bytes),
+            max_content_chars: env_or("MAX_CONTENT_CHARS", defaults.max_content_chars)
+        };
+    }
+
+    /// Returns `content` in Unicode NFC with surrounding whitespace trimmed
+    /// and `\r\n` line endings folded to `\n`, or why it cannot be posted.
+    pub fn validate_content(&self, content: &str) -> Result<String, ContentError> {
+        let normalized: String = content.replace("\r\n", "\n").nfc().collect();
+        let trimmed = normalized.trim();
+
+        if trimmed.is_empty() {
+            return Err(ContentError::Empty);
+        }
+
+        if trimmed.chars().count() > self.max_content_chars {
+            return Err(ContentError::TooLong { max_chars: self.max_content_chars });
+        }
+
+        if trimmed.chars().any(|c| c.is_control() && c != '\n' && c != '\t') {
+            return Err(ContentError::ControlCharacter);
+        }
+
+        return Ok(trimmed.to_string());
+    }
+
+    /// Runs client content through the whole inbound pipeline: markup is
+    /// stripped, what is left is validated, and links are extracted so
+    /// clients never have to parse the text themselves.
+    pub fn sanitize(&self, content: &str) -> Result<SanitizedContent, ContentError> {
+        let content = self.validate_content(&strip_html(content))?;
+        let links = extract_links(&content)?;
+
+        return Ok(SanitizedContent { content, links });
+    }
+}
\ No newline at end of file
@@ -0,0 +1,63 @@
+/// Joins emoji into one sequence, e.g. 👩‍💻.
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+/// Requests emoji presentation for the preceding character.
+const VARIATION_SELECTOR_16: char = '\u{FE0F}';
+/// Turns a preceding digit, `#` or `*` into a keycap.
+const COMBINING_KEYCAP: char = '\u{20E3}';
+
+/// Whether `text` is exactly one emoji: a pictograph with optional
+/// presentation selector, skin tone and tag modifiers, several of those
+/// joined by zero width joiners, a flag made of two regional indicators, or
+/// a keycap. Plain text such as "lol" or "a" is refused.
+pub fn is_single_emoji(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+
+    if chars.len() == 2 && chars.iter().all(|c| is_regional_indicator(*c)) {
+        return true;
+    }
+
+    if let [base, rest @ ..] = chars.as_slice()
+        && (base.is_ascii_digit() || *base == '#' || *base == '*') {
+        return matches!(rest, [COMBINING_KEYCAP] | [VARIATION_SELECTOR_16, COMBINING_KEYCAP]);
+    }
+
+    return text.split(ZERO_WIDTH_JOINER).all(is_modified_pictograph);
+}
+
+/// One pictograph followed only by modifiers.
+fn is_modified_pictograph(segment: &str) -> bool {
+    let mut chars = segment.chars();
+    let Some(base) = chars.next() else {
+        return false;
+    };
+
+    return is_pictograph(base) && chars.all(is_modifier);
+}
+
+fn is_pictograph(c: char) -> bool {
+    if is_regional_indicator(c) || is_skin_tone(c) {
+        return false;
+    }
+
+    return matches!(c,
+        '\u{00A9}' | '\u{00AE}' | '\u{203C}' | '\u{2049}' | '\u{2122}' | '\u{2139}'
+        | '\u{2194}'..='\u{2199}' | '\u{21A9}'..='\u{21AA}' | '\u{231A}'..='\u{231B}'
+        | '\u{2328}' | '\u{23CF}' | '\u{23E9}'..='\u{23F3}' | '\u{23F8}'..='\u{23FA}'
+        | '\u{24C2}' | '\u{25AA}'..='\u{25AB}' | '\u{25B6}' | '\u{25C0}' | '\u{25FB}'..='\u{25FE}'
+        | '\u{2600}'..='\u{27BF}' | '\u{2934}'..='\u{2935}' | '\u{2B05}'..='\u{2B07}'
+        | '\u{2B1B}'..='\u{2B1C}' | '\u{2B50}' | '\u{2B55}' | '\u{3030}' | '\u{303D}'
+        | '\u{3297}' | '\u{3299}' | '\u{1F000}'..='\u{1FAFF}' | '\u{1FC00}'..='\u{1FFFD}');
+}
+
+/// Presentation selector, skin tone or subdivision flag tag.
+fn is_modifier(c: char) -> bool {
+    return c == VARIATION_SELECTOR_16 || is_skin_tone(c) || matches!(c, '\u{E0020}'..='\u{E007F}');
+}
+
+fn is_skin_tone(c: char) -> bool {
+    return matches!(c, '\u{1F3FB}'..='\u{1F3FF}');
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    return matches!(c, '\u{1F1E6}'..='\u{1F1FF}');
+}
\ No newline at end of file
@@ -0,0 +1,78 @@
+use crate::config::env_secs;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use std::env;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// auth-service's answer for a member of a session.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Membership {
+    pub coach_id: Uuid
+}
+
+/// Asks auth-service whether a user belongs to a session, for sessions whose
+/// `session.created`/`session.joined` events this instance never saw (e.g.
+/// after a restart). Disabled unless `MEMBERSHIP_LOOKUP_URL` is set.
+pub struct MembershipLookup {
+    client: Client,
+    /// URL with `{session_id}` and `{user_id}` placeholders, normally
+    /// auth-service's `/v1/internal/sessions/{session_id}/members/{user_id}`.
+    /// The endpoint must answer 2xx with a `Membership` body for members and
+    /// 404 for everyone else.
+    url_template: Option<String>,
+    internal_secret: Option<String>
+}
+
+impl MembershipLookup {
+    pub fn new(url_template: Option<String>, internal_secret: Option<String>, timeout: Duration) -> Self {
+        let client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_default();
+
+        MembershipLookup { client, url_template, internal_secret }
+    }
+
+    pub fn from_env() -> Self {
+        let url_template = env::var("MEMBERSHIP_LOOKUP_URL").ok().filter(|url| !url.is_empty());
+        if url_template.is_none() {
+            println!("Membership lookup fallback disabled, relying on NATS events only");
+        }
+
+        return Self::new(
+            url_template,
+            env::var("INTERNAL_SHARED_SECRET").ok().filter(|secret| !secret.is_empty()),
+            env_secs("MEMBERSHIP_LOOKUP_TIMEOUT_SECS", 2)
+        );
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.url_template.is_some();
+    }
+
+    /// `Ok(None)` when the user is not a member or the fallback is disabled;
+    /// `Err` when auth-service could not be asked or refused the secret.
+    pub async fn lookup(&self, session_id: Uuid, user_id: Uuid) -> Result<Option<Membership>, reqwest::Error> {
+        let Some(template) = &self.url_template else {
+            return Ok(None);
+        };
+
+        let url = template
+            .replace("{session_id}", &session_id.to_string())
+            .replace("{user_id}", &user_id.to_string());
+
+        let mut request = self.client.get(&url);
+        if let Some(secret) = &self.internal_secret {
+            request = request.header("X-Internal-Secret", secret);
+        }
+
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let membership = response.error_for_status()?.json::<Membership>().await?;
+        return Ok(Some(membership));
+    }
+}
\ No newline at end of file
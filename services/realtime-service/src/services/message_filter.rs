@@ -0,0 +1,245 @@
+use crate::config::env_or;
+use crate::services::content_validation::SanitizedContent;
+use crate::services::sanitizer::extract_links;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::str::FromStr;
+use std::{env, fs, io};
+
+/// What one filter decided about a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterOutcome {
+    Allow,
+    /// Deliver this content instead; later filters see the masked text.
+    Mask(String),
+    /// Refuse the message. `reason` is shown to the sender.
+    Reject { reason: String },
+    /// Deliver the message but report it for review.
+    Flag { reason: String },
+}
+
+/// What a configured filter does with content it matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    Mask,
+    Reject,
+    Flag
+}
+
+impl FromStr for FilterAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        return match value.to_ascii_lowercase().as_str() {
+            "mask" => Ok(FilterAction::Mask),
+            "reject" => Ok(FilterAction::Reject),
+            "flag" => Ok(FilterAction::Flag),
+            other => Err(format!("unknown filter action '{}'", other)),
+        };
+    }
+}
+
+/// A moderation check run on message content before it is published or
+/// broadcast. Filters see sanitized plain text.
+pub trait MessageFilter: Send + Sync {
+    /// Reported in rejections and review flags.
+    fn name(&self) -> &str;
+    fn check(&self, content: &str) -> FilterOutcome;
+}
+
+/// Why a delivered message was reported for review.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FilterFlag {
+    pub filter: String,
+    pub reason: String
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FilterVerdict {
+    Deliver { content: SanitizedContent, flags: Vec<FilterFlag> },
+    Reject { filter: String, reason: String },
+}
+
+/// Filters applied in order to every chat message, direct message and edit.
+/// A rejection stops the pipeline; masks and flags accumulate.
+pub struct FilterPipeline {
+    filters: Vec<Box<dyn MessageFilter>>
+}
+
+impl FilterPipeline {
+    pub fn new(filters: Vec<Box<dyn MessageFilter>>) -> Self {
+        return FilterPipeline { filters };
+    }
+
+    /// Builds the pipeline from local files: the word list at
+    /// `FILTER_WORD_LIST_PATH` (applied with `FILTER_WORD_LIST_ACTION`,
+    /// `mask` by default) runs first, then the rules at `FILTER_RULES_PATH`
+    /// in file order. Both are optional; a file that cannot be loaded is an
+    /// error so the service never runs with moderation silently off.
+    pub fn from_env() -> io::Result<Self> {
+        let mut filters: Vec<Box<dyn MessageFilter>> = Vec::new();
+
+        if let Ok(path) = env::var("FILTER_WORD_LIST_PATH") {
+            let action = env_or("FILTER_WORD_LIST_ACTION", FilterAction::Mask);
+            filters.push(Box::new(WordListFilter::load(&path, action)?));
+        }
+
+        if let Ok(path) = env::var("FILTER_RULES_PATH") {
+            for filter in RegexFilter::load(&path)? {
+                filters.push(Box::new(filter));
+            }
+        }
+
+        println!("🛡️  Loaded {} message filters", filters.len());
+        return Ok(FilterPipeline::new(filters));
+    }
+
+    pub fn apply(&self, content: SanitizedContent) -> FilterVerdict {
+        let SanitizedContent { mut content, mut links } = content;
+        let mut flags = Vec::new();
+        let mut masked = false;
+
+        for filter in &self.filters {
+            match filter.check(&content) {
+                FilterOutcome::Allow => {}
+                FilterOutcome::Mask(replacement) => {
+                    content = replacement;
+                    masked = true;
+                }
+                FilterOutcome::Reject { reason } => {
+                    return FilterVerdict::Reject { filter: filter.name().to_string(), reason };
+                }
+                FilterOutcome::Flag { reason } => {
+                    flags.push(FilterFlag { filter: filter.name().to_string(), reason });
+                }
+            }
+        }
+
+        // Masking only replaces characters with `*`, so it cannot introduce
+        // a blocked scheme; link offsets just need recomputing.
+        if masked {
+            links = extract_links(&content).unwrap_or_default();
+        }
+
+        return FilterVerdict::Deliver { content: SanitizedContent { content, links }, flags };
+    }
+}
+
+/// Blocks whole words from a list, case-insensitively.
+pub struct WordListFilter {
+    words: HashSet<String>,
+    action: FilterAction
+}
+
+impl WordListFilter {
+    pub fn new<'a>(words: impl IntoIterator<Item = &'a str>, action: FilterAction) -> Self {
+        let words = words.into_iter().map(|word| word.trim().to_lowercase()).filter(|word| !word.is_empty()).collect();
+        return WordListFilter { words, action };
+    }
+
+    /// Reads one word per line; blank lines and `#` comments are skipped.
+    pub fn load(path: &str, action: FilterAction) -> io::Result<Self> {
+        let list = fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        let words = list.lines().filter(|line| !line.trim_start().starts_with('#'));
+
+        return Ok(WordListFilter::new(words, action));
+    }
+}
+
+impl MessageFilter for WordListFilter {
+    fn name(&self) -> &str {
+        return "word_list";
+    }
+
+    fn check(&self, content: &str) -> FilterOutcome {
+        let matches: Vec<Range<usize>> = content
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty() && self.words.contains(&word.to_lowercase()))
+            .map(|word| {
+                let start = word.as_ptr() as usize - content.as_ptr() as usize;
+                start..start + word.len()
+            })
+            .collect();
+
+        return outcome(self.action, content, &matches, "Message contains blocked words");
+    }
+}
+
+/// One named pattern, such as phone numbers or email addresses.
+pub struct RegexFilter {
+    name: String,
+    pattern: Regex,
+    action: FilterAction
+}
+
+impl RegexFilter {
+    pub fn new(name: &str, pattern: Regex, action: FilterAction) -> Self {
+        return RegexFilter { name: name.to_string(), pattern, action };
+    }
+
+    /// Reads one rule per line as `<action> <name> <pattern>`, for example
+    /// `mask email [\w.+-]+@[\w-]+(\.[\w-]+)+`. Blank lines and `#` comments
+    /// are skipped; the pattern is the rest of the line.
+    pub fn load(path: &str) -> io::Result<Vec<Self>> {
+        let rules = fs::read_to_string(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        let mut filters = Vec::new();
+
+        for (index, line) in rules.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path, index + 1, message));
+
+            let (action, rest) = line.split_once(char::is_whitespace).ok_or_else(|| invalid("expected <action> <name> <pattern>".to_string()))?;
+            let (name, pattern) = rest.trim_start().split_once(char::is_whitespace).ok_or_else(|| invalid("missing pattern".to_string()))?;
+            let action = action.parse::<FilterAction>().map_err(invalid)?;
+            let pattern = Regex::new(pattern.trim()).map_err(|e| invalid(e.to_string()))?;
+
+            filters.push(RegexFilter::new(name, pattern, action));
+        }
+
+        return Ok(filters);
+    }
+}
+
+impl MessageFilter for RegexFilter {
+    fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    fn check(&self, content: &str) -> FilterOutcome {
+        let matches: Vec<Range<usize>> = self.pattern.find_iter(content).map(|m| m.range()).collect();
+        let reason = format!("Message blocked by the {} filter", self.name);
+
+        return outcome(self.action, content, &matches, &reason);
+    }
+}
+
+/// Applies `action` to the byte ranges a filter matched.
+fn outcome(action: FilterAction, content: &str, matches: &[Range<usize>], reason: &str) -> FilterOutcome {
+    if matches.is_empty() {
+        return FilterOutcome::Allow;
+    }
+
+    return match action {
+        FilterAction::Mask => FilterOutcome::Mask(mask(content, matches)),
+        FilterAction::Reject => FilterOutcome::Reject { reason: reason.to_string() },
+        FilterAction::Flag => {
+            let matched: Vec<&str> = matches.iter().map(|range| &content[range.clone()]).collect();
+            FilterOutcome::Flag { reason: format!("matched {:?}", matched) }
+        }
+    };
+}
+
+/// Replaces every character inside `matches` with `*`, keeping the length
+/// in characters unchanged.
+fn mask(content: &str, matches: &[Range<usize>]) -> String {
+    return content
+        .char_indices()
+        .map(|(index, c)| if matches.iter().any(|range| range.contains(&index)) { '*' } else { c })
+        .collect();
+}
\ No newline at end of file
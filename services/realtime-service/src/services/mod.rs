@@ -1 +1,10 @@
-pub mod session_manager;
\ No newline at end of file
+pub mod content_validation;
+pub mod emoji;
+pub mod membership;
+pub mod message_filter;
+pub mod rate_limiter;
+pub mod sanitizer;
+pub mod session_manager;
+pub mod session_registry;
+pub mod state_eviction;
+pub mod typing_expiry;
\ No newline at end of file
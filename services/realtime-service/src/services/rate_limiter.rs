@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+/// Sustained rate and burst allowance of a `TokenBucket`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Messages that can be sent back to back after being idle.
+    pub burst: f64,
+    /// Tokens regained per second; zero or less disables the limit.
+    pub per_second: f64
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled_at: Instant
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        TokenBucket { limit, tokens: limit.burst, refilled_at: now }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst);
+        self.refilled_at = now;
+    }
+
+    /// How long until a token is available, or `None` if one is available now.
+    pub fn wait_time(&mut self, now: Instant) -> Option<Duration> {
+        if self.limit.per_second <= 0.0 {
+            return None;
+        }
+
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            return None;
+        }
+
+        return Some(Duration::try_from_secs_f64((1.0 - self.tokens) / self.limit.per_second).unwrap_or(Duration::MAX));
+    }
+
+    /// Spends a token; callers check `wait_time` first.
+    pub fn take(&mut self, now: Instant) {
+        if self.limit.per_second > 0.0 {
+            self.refill(now);
+            self.tokens = (self.tokens - 1.0).max(0.0);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,288 @@
+use crate::model::chat_message::LinkEntity;
+use crate::services::content_validation::ContentError;
+use url::Url;
+
+/// Characters that commonly wrap a link in prose and are not part of it.
+const LEADING_PUNCTUATION: &[char] = &['(', '[', '{', '<', '"', '\''];
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', '"', '\''];
+
+/// HTML element names a client could render. Only these count as tags, so
+/// generics like `Vec<String>` and comparisons like `a<b and c>d` survive.
+const HTML_ELEMENTS: &[&str] = &[
+    "a", "abbr", "address", "applet", "area", "article", "aside", "audio", "b", "base", "basefont",
+    "bdi", "bdo", "bgsound", "big", "blink", "blockquote", "body", "br", "button", "canvas", "caption",
+    "center", "cite", "code", "col", "colgroup", "data", "datalist", "dd", "del", "details", "dfn",
+    "dialog", "dir", "div", "dl", "dt", "em", "embed", "fieldset", "figcaption", "figure", "font",
+    "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5", "h6", "head", "header",
+    "hgroup", "hr", "html", "i", "iframe", "image", "img", "input", "ins", "isindex", "kbd", "keygen",
+    "label", "legend", "li", "link", "listing", "main", "map", "mark", "marquee", "math", "menu",
+    "meta", "meter", "nav", "nobr", "noembed", "noframes", "noscript", "object", "ol", "optgroup",
+    "option", "output", "p", "param", "picture", "plaintext", "pre", "progress", "q", "rp", "rt",
+    "ruby", "s", "samp", "script", "search", "section", "select", "slot", "small", "source", "span",
+    "strike", "strong", "style", "sub", "summary", "sup", "svg", "table", "tbody", "td", "template",
+    "textarea", "tfoot", "th", "thead", "time", "title", "tr", "track", "tt", "u", "ul", "var", "video",
+    "wbr", "xmp",
+];
+
+/// Attribute names accepted inside a tag, besides `on*`, `data-*` and
+/// `aria-*`. Anything else means the `<` was prose, not markup.
+const HTML_ATTRIBUTES: &[&str] = &[
+    "accept", "action", "align", "allow", "alt", "autofocus", "autoplay", "background", "bgcolor",
+    "border", "charset", "checked", "cite", "class", "color", "cols", "colspan", "content", "controls",
+    "coords", "crossorigin", "datetime", "decoding", "dir", "disabled", "download", "draggable",
+    "enctype", "face", "for", "form", "formaction", "frameborder", "height", "hidden", "href",
+    "hreflang", "http-equiv", "id", "integrity", "is", "itemprop", "label", "lang", "loading", "loop",
+    "max", "maxlength", "media", "method", "min", "multiple", "muted", "name", "nonce", "open",
+    "pattern", "ping", "placeholder", "poster", "preload", "readonly", "referrerpolicy", "rel",
+    "required", "role", "rows", "rowspan", "sandbox", "scope", "selected", "shape", "size", "sizes",
+    "slot", "span", "src", "srcdoc", "srclang", "srcset", "start", "step", "style", "tabindex",
+    "target", "title", "type", "usemap", "value", "width", "wrap", "xmlns",
+];
+
+/// Removes HTML tags and comments so content is always plain text. A `<`
+/// that does not open a tag (`a < b`, `<3`, `Vec<String>`,
+/// `<https://...>`) is kept. Runs in one pass: when a tag is removed, any
+/// kept `<` it directly follows is dropped too, so nested input such as
+/// `<scr<script>ipt>` cannot reassemble a tag.
+pub fn strip_html(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    // Kept `<`s followed only by characters that could still grow into a
+    // tag name, oldest first.
+    let mut open_fragments: Vec<usize> = Vec::new();
+    let mut comments_can_close = true;
+    let mut rest = content;
+
+    while let Some(open) = rest.find('<') {
+        push_text(&mut output, &mut open_fragments, &rest[..open]);
+        let candidate = &rest[open..];
+
+        match tag_len(candidate, &mut comments_can_close) {
+            Some(len) => {
+                if let Some(&first) = open_fragments.first() {
+                    output.truncate(first);
+                    open_fragments.clear();
+                }
+                rest = &candidate[len..];
+            }
+            None => {
+                open_fragments.push(output.len());
+                output.push('<');
+                rest = &candidate[1..];
+            }
+        }
+    }
+    push_text(&mut output, &mut open_fragments, rest);
+
+    return output;
+}
+
+/// Appends text without tags, forgetting kept `<`s it turns into prose.
+fn push_text(output: &mut String, open_fragments: &mut Vec<usize>, text: &str) {
+    if !text.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '!' | '-')) {
+        open_fragments.clear();
+    }
+    output.push_str(text);
+}
+
+/// Byte length of the tag or comment `text` starts with, if it starts with
+/// one. `comments_can_close` is cleared once no `-->` is left, so runs of
+/// unterminated comments are not rescanned.
+fn tag_len(text: &str, comments_can_close: &mut bool) -> Option<usize> {
+    if let Some(comment) = text.strip_prefix("<!--") {
+        if !*comments_can_close {
+            return None;
+        }
+        let end = comment.find("-->");
+        *comments_can_close = end.is_some();
+        return end.map(|end| 4 + end + 3);
+    }
+
+    let (body, closing) = match text.strip_prefix("</") {
+        Some(body) => (body, true),
+        None => (text.strip_prefix('<')?, false),
+    };
+
+    let name_len = body.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(body.len());
+    let name = body[..name_len].to_ascii_lowercase();
+    if !HTML_ELEMENTS.contains(&name.as_str()) {
+        return None;
+    }
+
+    let after_name = &body[name_len..];
+    let attributes_len = if closing {
+        after_name.len() - after_name.trim_start().len()
+    } else {
+        attributes_len(after_name)?
+    };
+
+    let end = &after_name[attributes_len..];
+    let end_len = if end.starts_with('>') {
+        1
+    } else if end.starts_with("/>") && !closing {
+        2
+    } else {
+        return None;
+    };
+
+    return Some(text.len() - end.len() + end_len);
+}
+
+/// Byte length of the whitespace separated attributes at the start of
+/// `text`, or `None` if something other than a known attribute follows.
+fn attributes_len(text: &str) -> Option<usize> {
+    let mut rest = text;
+
+    loop {
+        let trimmed = rest.trim_start();
+        if trimmed.starts_with('>') || trimmed.starts_with("/>") {
+            return Some(text.len() - trimmed.len());
+        }
+        if trimmed.len() == rest.len() {
+            return None;
+        }
+
+        let name_len = trimmed
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == ':'))
+            .unwrap_or(trimmed.len());
+        if !is_html_attribute(&trimmed[..name_len].to_ascii_lowercase()) {
+            return None;
+        }
+
+        rest = &trimmed[name_len..];
+        if let Some(value) = rest.strip_prefix('=') {
+            rest = &value[attribute_value_len(value)?..];
+        }
+    }
+}
+
+fn is_html_attribute(name: &str) -> bool {
+    return HTML_ATTRIBUTES.contains(&name)
+        || (name.len() > 2 && name.starts_with("on"))
+        || name.starts_with("data-")
+        || name.starts_with("aria-");
+}
+
+/// Byte length of a quoted or unquoted attribute value.
+fn attribute_value_len(text: &str) -> Option<usize> {
+    if let Some(quote) = text.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        return text[1..].find(quote).map(|end| end + 2);
+    }
+
+    let len = text
+        .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`'))
+        .unwrap_or(text.len());
+    return (len > 0).then_some(len);
+}
+
+/// Finds the `http(s)://` and `www.` links in `content`, or the first token
+/// using a blocked scheme. Entity offsets are UTF-16 code units so browser
+/// and mobile clients can slice `content` without re-parsing it.
+pub fn extract_links(content: &str) -> Result<Vec<LinkEntity>, ContentError> {
+    let mut links = Vec::new();
+
+    for (offset, token) in tokens(content) {
+        let token_start = token.len() - token.trim_start_matches(LEADING_PUNCTUATION).len();
+        let word = &token[token_start..];
+
+        if let Some(scheme) = blocked_scheme(word) {
+            return Err(ContentError::UnsafeLink { scheme });
+        }
+
+        let candidate = trim_trailing(word);
+        let Some(url) = parse_link(candidate) else {
+            continue;
+        };
+
+        let start = utf16_len(&content[..offset + token_start]);
+        links.push(LinkEntity {
+            url: url.to_string(),
+            start,
+            end: start + utf16_len(candidate),
+        });
+    }
+
+    return Ok(links);
+}
+
+/// Whitespace-separated tokens with their byte offsets.
+fn tokens(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    return content
+        .split(char::is_whitespace)
+        .filter(|token| !token.is_empty())
+        .map(move |token| (token.as_ptr() as usize - content.as_ptr() as usize, token));
+}
+
+/// The first blocked scheme in `word` used as a link target, wherever it
+/// starts after a non-alphanumeric character, so markdown links like
+/// `[x](javascript:...)` and `href=data:...` are caught while prose like
+/// "data: see below" or "file:main.rs" is still accepted.
+fn blocked_scheme(word: &str) -> Option<String> {
+    for (colon, _) in word.match_indices(':') {
+        let (prefix, payload) = (&word[..colon], &word[colon + 1..]);
+        let start = prefix.trim_end_matches(|c: char| c.is_ascii_alphabetic()).len();
+        let scheme = prefix[start..].to_ascii_lowercase();
+
+        let at_boundary = !prefix[..start].ends_with(char::is_alphanumeric);
+        if at_boundary && is_link_target(&scheme, payload) {
+            return Some(scheme);
+        }
+    }
+
+    return None;
+}
+
+/// Whether `payload` is what a browser would follow for `scheme`: any script
+/// for `javascript:`/`vbscript:`, a comma separated body for `data:`, and
+/// a path for `file:`.
+fn is_link_target(scheme: &str, payload: &str) -> bool {
+    return match scheme {
+        "javascript" | "vbscript" => !payload.is_empty(),
+        "data" => payload.contains(','),
+        "file" => payload.starts_with('/') || payload.starts_with('\\'),
+        _ => false,
+    };
+}
+
+/// Drops sentence punctuation and unbalanced closing brackets from the end
+/// of a link candidate.
+fn trim_trailing(word: &str) -> &str {
+    let mut candidate = word;
+
+    loop {
+        let trimmed = candidate.trim_end_matches(TRAILING_PUNCTUATION);
+        let trimmed = match trimmed.chars().last() {
+            Some(')') if trimmed.matches(')').count() > trimmed.matches('(').count() => &trimmed[..trimmed.len() - 1],
+            Some(']') if trimmed.matches(']').count() > trimmed.matches('[').count() => &trimmed[..trimmed.len() - 1],
+            Some('>') => &trimmed[..trimmed.len() - 1],
+            _ => trimmed,
+        };
+
+        if trimmed.len() == candidate.len() {
+            return candidate;
+        }
+        candidate = trimmed;
+    }
+}
+
+fn parse_link(candidate: &str) -> Option<Url> {
+    let lower = candidate.to_ascii_lowercase();
+
+    let url = if lower.starts_with("http://") || lower.starts_with("https://") {
+        Url::parse(candidate).ok()?
+    } else if lower.starts_with("www.") {
+        Url::parse(&format!("https://{}", candidate)).ok()?
+    } else {
+        return None;
+    };
+
+    let host = url.host_str()?;
+    if !host.contains('.') || host.ends_with('.') {
+        return None;
+    }
+
+    return Some(url);
+}
+
+fn utf16_len(text: &str) -> usize {
+    return text.chars().map(char::len_utf16).sum();
+}
\ No newline at end of file
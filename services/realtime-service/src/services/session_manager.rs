@@ -1,81 +1,1023 @@
 use crate::auth::jwt::Claims;
-use std::collections::HashMap;
+use crate::config::{env_or, env_secs};
+use crate::model::chat_message::{BroadcastMessage, ChatMessage, DirectMessage, QuotedMessage, ReactionCount, SenderInfo};
+use crate::model::presence::Participant;
+use chrono::{DateTime, Utc};
+use crate::model::protocol::{CloseCode, ServerMessage};
+use crate::services::content_validation::SanitizedContent;
+use crate::services::emoji::is_single_emoji;
+use crate::services::rate_limiter::{RateLimit, TokenBucket};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::sync::Mutex;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
 use uuid::Uuid;
 
 pub struct Connection {
     pub sender: mpsc::Sender<String>,
-    pub user_info: Claims
+    pub user_info: Claims,
+    /// Fired at most once when the server ends the connection, e.g. on a kick.
+    pub closer: Option<oneshot::Sender<CloseCode>>
+}
+
+/// Tunables for per-session state, read from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct SessionManagerConfig {
+    /// How long a chat message's `client_msg_id` is remembered for dedupe
+    /// (`IDEMPOTENCY_WINDOW_SECS`).
+    pub idempotency_window: Duration,
+    /// How long a session whose last connection left is kept so numbering
+    /// continues on reconnect (`IDLE_SESSION_RETENTION_SECS`).
+    pub idle_retention: Duration,
+    /// Number of recent chat messages kept per session and replayed to new
+    /// connections (`HISTORY_REPLAY_LIMIT`).
+    pub history_limit: usize,
+    /// Messages older than this are not replayed (`HISTORY_REPLAY_MAX_AGE_SECS`).
+    pub history_max_age: Duration,
+    /// A typing indicator not refreshed within this long is cleared
+    /// (`TYPING_TIMEOUT_SECS`).
+    pub typing_timeout: Duration,
+    /// How long a `typing_stop` waits before it is broadcast, so a client
+    /// toggling between start and stop does not fan out every frame
+    /// (`TYPING_STOP_DELAY_SECS`).
+    pub typing_stop_delay: Duration,
+    /// Distinct emoji a single message can collect (`MAX_REACTIONS_PER_MESSAGE`).
+    pub max_reactions_per_message: usize,
+    /// Posting rate of one user in one session (`USER_RATE_BURST`,
+    /// `USER_RATE_PER_SEC`).
+    pub user_rate_limit: RateLimit,
+    /// Posting rate of a whole session (`SESSION_RATE_BURST`,
+    /// `SESSION_RATE_PER_SEC`).
+    pub session_rate_limit: RateLimit,
+    /// Rate of edits, reactions and read receipts of one user in one session
+    /// (`ACTION_RATE_BURST`, `ACTION_RATE_PER_SEC`).
+    pub action_rate_limit: RateLimit,
+    /// Abuse reports one user may file in one session (`REPORT_RATE_BURST`,
+    /// `REPORT_RATE_PER_SEC`).
+    pub report_rate_limit: RateLimit
+}
+
+impl Default for SessionManagerConfig {
+    fn default() -> Self {
+        SessionManagerConfig {
+            idempotency_window: Duration::from_secs(300),
+            idle_retention: Duration::from_secs(24 * 60 * 60),
+            history_limit: 50,
+            history_max_age: Duration::from_secs(3600),
+            typing_timeout: Duration::from_secs(6),
+            typing_stop_delay: Duration::from_secs(1),
+            max_reactions_per_message: 20,
+            user_rate_limit: RateLimit { burst: 5.0, per_second: 1.0 },
+            session_rate_limit: RateLimit { burst: 30.0, per_second: 10.0 },
+            action_rate_limit: RateLimit { burst: 10.0, per_second: 2.0 },
+            report_rate_limit: RateLimit { burst: 3.0, per_second: 1.0 / 60.0 }
+        }
+    }
+}
+
+impl SessionManagerConfig {
+    pub fn from_env() -> Self {
+        let defaults = SessionManagerConfig::default();
+
+        return SessionManagerConfig {
+            idempotency_window: env_secs("IDEMPOTENCY_WINDOW_SECS", defaults.idempotency_window.as_secs()),
+            idle_retention: env_secs("IDLE_SESSION_RETENTION_SECS", defaults.idle_retention.as_secs()),
+            history_limit: env_or("HISTORY_REPLAY_LIMIT", defaults.history_limit),
+            history_max_age: env_secs("HISTORY_REPLAY_MAX_AGE_SECS", defaults.history_max_age.as_secs()),
+            typing_timeout: env_secs("TYPING_TIMEOUT_SECS", defaults.typing_timeout.as_secs()),
+            typing_stop_delay: env_secs("TYPING_STOP_DELAY_SECS", defaults.typing_stop_delay.as_secs()),
+            max_reactions_per_message: env_or("MAX_REACTIONS_PER_MESSAGE", defaults.max_reactions_per_message),
+            user_rate_limit: RateLimit {
+                burst: env_or("USER_RATE_BURST", defaults.user_rate_limit.burst),
+                per_second: env_or("USER_RATE_PER_SEC", defaults.user_rate_limit.per_second)
+            },
+            session_rate_limit: RateLimit {
+                burst: env_or("SESSION_RATE_BURST", defaults.session_rate_limit.burst),
+                per_second: env_or("SESSION_RATE_PER_SEC", defaults.session_rate_limit.per_second)
+            },
+            action_rate_limit: RateLimit {
+                burst: env_or("ACTION_RATE_BURST", defaults.action_rate_limit.burst),
+                per_second: env_or("ACTION_RATE_PER_SEC", defaults.action_rate_limit.per_second)
+            },
+            report_rate_limit: RateLimit {
+                burst: env_or("REPORT_RATE_BURST", defaults.report_rate_limit.burst),
+                per_second: env_or("REPORT_RATE_PER_SEC", defaults.report_rate_limit.per_second)
+            }
+        };
+    }
+}
+
+/// Server-side identity of an accepted chat or direct message, replayed to
+/// clients that resubmit the same `client_msg_id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeliveryReceipt {
+    pub message_id: Uuid,
+    /// Absent for direct messages, which sit outside the session sequence.
+    pub seq: Option<u64>,
+    pub sent_at: DateTime<Utc>
+}
+
+impl From<&BroadcastMessage> for DeliveryReceipt {
+    fn from(message: &BroadcastMessage) -> Self {
+        DeliveryReceipt {
+            message_id: message.id,
+            seq: Some(message.seq),
+            sent_at: message.sent_at
+        }
+    }
+}
+
+impl From<&DirectMessage> for DeliveryReceipt {
+    fn from(message: &DirectMessage) -> Self {
+        DeliveryReceipt {
+            message_id: message.id,
+            seq: None,
+            sent_at: message.sent_at
+        }
+    }
+}
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)] // `Fresh` is the common case; boxing it buys nothing
+pub enum StampedMessage {
+    /// First submission: publish and broadcast it.
+    Fresh(BroadcastMessage),
+    /// Retry of a message already accepted within the idempotency window.
+    Duplicate(DeliveryReceipt)
+}
+
+/// Buffered conversation captured for an abuse report.
+#[derive(Debug, Clone)]
+pub struct ReportContext {
+    /// The reported message, when the report names one.
+    pub message: Option<BroadcastMessage>,
+    /// The reported direct message, when the report names one sent to the
+    /// reporter.
+    pub direct_message: Option<DirectMessage>,
+    pub reported_user_id: Uuid,
+    /// Messages around the reported one, or the reported user's latest
+    /// messages when no message is named, oldest first.
+    pub context: Vec<BroadcastMessage>
+}
+
+/// Why an edit or delete of a buffered message was refused.
+#[derive(Debug, PartialEq)]
+pub enum MessageChangeError {
+    /// Not in the replay buffer, or already deleted.
+    NotFound,
+    /// Only the original sender or the session's coach may change a message.
+    Forbidden
+}
+
+/// Why a reaction was refused.
+#[derive(Debug, PartialEq)]
+pub enum ReactionError {
+    /// Not in the replay buffer, or deleted.
+    MessageNotFound,
+    /// Not exactly one emoji, or a sequence longer than `MAX_EMOJI_CHARS`.
+    InvalidEmoji,
+    /// The message already has `max_reactions_per_message` distinct emoji.
+    LimitReached
+}
+
+/// Longest emoji sequence accepted, in characters; generous enough for
+/// skin-tone and ZWJ sequences.
+const MAX_EMOJI_CHARS: usize = 16;
+
+/// Length of the parent excerpt quoted in replies, in characters.
+const QUOTE_EXCERPT_CHARS: usize = 120;
+
+/// Buffered messages captured on each side of a reported message.
+const REPORT_CONTEXT_MESSAGES: usize = 5;
+
+/// Direct messages kept per session so their recipients can report them.
+const REPORTABLE_DIRECT_MESSAGES: usize = 100;
+
+/// What a newly registered connection should receive before live traffic.
+#[derive(Debug)]
+pub enum Replay {
+    /// Chat messages to replay, oldest first. May be empty.
+    Messages(Vec<BroadcastMessage>),
+    /// The requested resume point is no longer buffered; the client has to
+    /// reload history out of band.
+    ResyncRequired { latest_seq: u64 }
+}
+
+#[derive(Default)]
+struct Session {
+    connections: HashMap<usize, Connection>,
+    last_seq: u64,
+    /// Keyed by (user id, client_msg_id) so retries from a new connection
+    /// of the same user still dedupe.
+    idempotency_keys: HashMap<(Uuid, String), (Instant, DeliveryReceipt)>,
+    /// Recent chat messages ordered by `seq`, bounded by `history_limit`.
+    history: VecDeque<(Instant, BroadcastMessage)>,
+    /// Recent direct messages, never replayed; only looked up for reports.
+    direct_messages: VecDeque<DirectMessage>,
+    /// Users currently typing and when their indicator expires.
+    typing: HashMap<Uuid, (Participant, Instant)>,
+    /// Highest `seq` each user has read.
+    read_marks: HashMap<Uuid, u64>,
+    /// Who reacted with which emoji, per buffered message.
+    reactions: HashMap<Uuid, BTreeMap<String, HashSet<Uuid>>>,
+    last_posted: HashMap<Uuid, Instant>,
+    user_buckets: HashMap<Uuid, TokenBucket>,
+    session_bucket: Option<TokenBucket>,
+    action_buckets: HashMap<Uuid, TokenBucket>,
+    report_buckets: HashMap<Uuid, TokenBucket>
+}
+
+impl Session {
+    fn record_history(&mut self, message: BroadcastMessage, config: &SessionManagerConfig) {
+        if config.history_limit == 0 {
+            return;
+        }
+
+        // Publishing happens between stamping and broadcasting, so concurrent
+        // senders can arrive slightly out of order.
+        let position = self.history.iter()
+            .rposition(|(_, existing)| existing.seq < message.seq)
+            .map_or(0, |index| index + 1);
+        self.history.insert(position, (Instant::now(), message));
+
+        while self.history.len() > config.history_limit {
+            if let Some((_, evicted)) = self.history.pop_front() {
+                self.reactions.remove(&evicted.id);
+            }
+        }
+    }
+
+    fn recent_history(&self, max_age: Duration) -> Vec<BroadcastMessage> {
+        return self.history.iter()
+            .filter(|(recorded_at, _)| recorded_at.elapsed() <= max_age)
+            .map(|(_, message)| message.clone())
+            .collect();
+    }
+
+    /// Everything after `last_seq`, provided the buffer still reaches back
+    /// that far. A `last_seq` ahead of this session's counter comes from an
+    /// earlier incarnation of the session and cannot be resumed either.
+    fn history_since(&self, last_seq: u64) -> Replay {
+        if last_seq > self.last_seq {
+            return Replay::ResyncRequired { latest_seq: self.last_seq };
+        }
+
+        if last_seq == self.last_seq {
+            return Replay::Messages(Vec::new());
+        }
+
+        match self.history.front() {
+            Some((_, oldest)) if oldest.seq <= last_seq + 1 => {
+                return Replay::Messages(self.history.iter()
+                    .filter(|(_, message)| message.seq > last_seq)
+                    .map(|(_, message)| message.clone())
+                    .collect());
+            }
+            _ => return Replay::ResyncRequired { latest_seq: self.last_seq },
+        }
+    }
+
+    /// Preview of a buffered, non-deleted message for quoting in replies.
+    fn quote(&self, message_id: Uuid) -> Option<QuotedMessage> {
+        let (_, parent) = self.history.iter()
+            .find(|(_, message)| message.id == message_id && message.deleted_at.is_none())?;
+
+        let mut excerpt: String = parent.content.chars().take(QUOTE_EXCERPT_CHARS).collect();
+        if excerpt.len() < parent.content.len() {
+            excerpt.push('…');
+        }
+
+        return Some(QuotedMessage { sender: parent.sender.clone(), excerpt });
+    }
+
+    /// Re-derives the previews of buffered replies to `message_id` after the
+    /// parent was edited or deleted, so replays never quote stale content.
+    fn refresh_quotes(&mut self, message_id: Uuid) {
+        let quoted = self.quote(message_id);
+
+        for (_, message) in self.history.iter_mut() {
+            if message.reply_to == Some(message_id) && message.quoted.is_some() {
+                message.quoted = quoted.clone();
+            }
+        }
+    }
+
+    fn find_message_mut(&mut self, message_id: Uuid) -> Option<&mut BroadcastMessage> {
+        return self.history.iter_mut()
+            .map(|(_, message)| message)
+            .find(|message| message.id == message_id && message.deleted_at.is_none());
+    }
+
+    fn is_user_connected(&self, user_id: Uuid) -> bool {
+        return self.connections.values().any(|conn| conn.user_info.sub == user_id);
+    }
+
+    /// Distinct users behind the session's connections, sorted by name.
+    fn participants(&self) -> Vec<Participant> {
+        let mut participants: Vec<Participant> = self.connections.values()
+            .map(|conn| (conn.user_info.sub, Participant::from(&conn.user_info)))
+            .collect::<HashMap<Uuid, Participant>>()
+            .into_values()
+            .collect();
+
+        participants.sort_by(|a, b| a.name.cmp(&b.name).then(a.user_id.cmp(&b.user_id)));
+        return participants;
+    }
+
+    fn fan_out(&self, session_id: Uuid, message: &str, skip_id: Option<usize>) {
+        println!("📡 Broadcasting to session {} (skip_id: {:?}). Total recipients: {}",
+            session_id, skip_id, self.connections.len());
+
+        let mut sent_count = 0;
+        let mut failed_count = 0;
+
+        for (id, conn) in &self.connections {
+            if skip_id.is_some() && skip_id.unwrap() == *id {
+                println!("⏭️  Skipping conn_id={} (sender)", id);
+                continue;
+            }
+
+            println!("📤 Attempting to send to conn_id={} ({})", id, conn.user_info.name);
+
+            match conn.sender.try_send(message.to_string()) {
+                Ok(_) => {
+                    sent_count += 1;
+                    println!("✅ Message sent to conn_id={}", id);
+                },
+                Err(e) => {
+                    failed_count += 1;
+                    eprintln!("❌ Failed to send message to connection {} ({}): {:?}",
+                        id, conn.user_info.name, e);
+                }
+            }
+        }
+
+        println!("📊 Broadcast summary: {} sent, {} failed", sent_count, failed_count);
+    }
+
+    fn fan_out_except_user(&self, session_id: Uuid, user_id: Uuid, message: &str) {
+        for (id, conn) in &self.connections {
+            if conn.user_info.sub == user_id {
+                continue;
+            }
+
+            if let Err(e) = conn.sender.try_send(message.to_string()) {
+                eprintln!("❌ Failed to send message to connection {} in session {}: {:?}", id, session_id, e);
+            }
+        }
+    }
+
+    fn stop_typing(&mut self, session_id: Uuid, user_id: Uuid) {
+        if let Some((participant, _)) = self.typing.remove(&user_id) {
+            let stopped = ServerMessage::Typing { participant, is_typing: false }.to_json();
+            self.fan_out_except_user(session_id, user_id, &stopped);
+        }
+    }
+
+    fn fan_out_to(&self, session_id: Uuid, conn_id: usize, message: &str) {
+        if let Some(conn) = self.connections.get(&conn_id)
+            && let Err(e) = conn.sender.try_send(message.to_string()) {
+            eprintln!("❌ Failed to send message to connection {} in session {}: {:?}", conn_id, session_id, e);
+        }
+    }
+}
+
+/// Spends a token from `user_id`'s bucket in `buckets`, or returns how long
+/// until one is available.
+fn take_token(buckets: &mut HashMap<Uuid, TokenBucket>, user_id: Uuid, limit: RateLimit) -> Result<(), Duration> {
+    let now = Instant::now();
+    let bucket = buckets.entry(user_id).or_insert_with(|| TokenBucket::new(limit, now));
+
+    if let Some(wait) = bucket.wait_time(now) {
+        return Err(wait);
+    }
+
+    bucket.take(now);
+    return Ok(());
+}
+
+/// A session whose last connection left, kept so numbering continues when
+/// someone reconnects. Its idempotency keys and history only survive for
+/// `idempotency_window`, so retries and resumes right after the room empties
+/// still work; after that only `last_seq` is restored. Dropped entirely
+/// after `idle_retention`.
+struct IdleSession {
+    emptied_at: Instant,
+    session: Session
+}
+
+impl IdleSession {
+    fn wake(self, window: Duration) -> Session {
+        if self.emptied_at.elapsed() < window {
+            return self.session;
+        }
+
+        return Session { last_seq: self.session.last_seq, ..Session::default() };
+    }
+
+    /// Frees the history and idempotency keys once `window` has passed.
+    fn shrink(&mut self, window: Duration) {
+        if self.emptied_at.elapsed() >= window {
+            self.session = Session { last_seq: self.session.last_seq, ..Session::default() };
+        }
+    }
 }
 
 pub struct SessionManager {
-    sessions: Mutex<HashMap<Uuid, HashMap<usize, Connection>>>
+    sessions: Mutex<HashMap<Uuid, Session>>,
+    /// Sessions whose last connection left. Dropped by `end_session` or
+    /// `expire_idle_sessions`.
+    idle_sessions: Mutex<HashMap<Uuid, IdleSession>>,
+    config: SessionManagerConfig
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        return SessionManager::new();
+    }
 }
 
 impl SessionManager {
     pub fn new() -> Self {
+        Self::with_config(SessionManagerConfig::from_env())
+    }
+
+    pub fn with_config(config: SessionManagerConfig) -> Self {
         SessionManager {
-            sessions: Mutex::new(HashMap::new())
+            sessions: Mutex::new(HashMap::new()),
+            idle_sessions: Mutex::new(HashMap::new()),
+            config
         }
     }
 
-    pub fn insert(&self, session_id: Uuid, conn_id: usize, conn: Connection) {
+    /// Registers a connection and returns what it should be sent before any
+    /// live traffic: the gap after `resume_from` for a reconnecting client,
+    /// otherwise the recent history. Both happen under one lock, so nothing
+    /// broadcast afterwards is missing from the channel or duplicated in the
+    /// replay.
+    pub fn insert(&self, session_id: Uuid, conn_id: usize, conn: Connection, resume_from: Option<u64>) -> Replay {
         let mut sessions = self.sessions.lock().unwrap();
-        let session = sessions.entry(session_id).or_insert_with(HashMap::new);
+        let session = sessions.entry(session_id).or_insert_with(|| {
+            let idle = self.idle_sessions.lock().unwrap().remove(&session_id);
+            idle.map(|idle| idle.wake(self.config.idempotency_window)).unwrap_or_default()
+        });
+
+        println!("👤 User '{}' ({}) joined session {}. Total connections: {}",
+            conn.user_info.name, conn.user_info.sub, session_id, session.connections.len() + 1);
+
+        let participant = Participant::from(&conn.user_info);
+        if !session.is_user_connected(participant.user_id) {
+            let joined = ServerMessage::ParticipantJoined(participant.clone()).to_json();
+            session.fan_out(session_id, &joined, None);
+        }
+
+        session.connections.insert(conn_id, conn);
 
-        println!("👤 User '{}' ({}) joined session {}. Total connections: {}", 
-            conn.user_info.name, conn.user_info.sub, session_id, session.len() + 1);
-        
-        session.insert(conn_id, conn);
+        let presence = ServerMessage::Presence { participants: session.participants() }.to_json();
+        session.fan_out_to(session_id, conn_id, &presence);
+
+        return match resume_from {
+            Some(last_seq) => session.history_since(last_seq),
+            None => Replay::Messages(session.recent_history(self.config.history_max_age)),
+        };
     }
 
     pub fn remove(&self, session_id: Uuid, conn_id: usize) {
         let mut sessions = self.sessions.lock().unwrap();
 
         if let Some(session) = sessions.get_mut(&session_id) {
-            session.remove(&conn_id);
+            let removed = session.connections.remove(&conn_id);
             println!("👋 Connection {} removed from session {}", conn_id, session_id);
 
-            if session.is_empty() {
-                sessions.remove(&session_id);
-                println!("🗑️  Session {} empty and removed", session_id);
+            if let Some(conn) = removed
+                && !session.connections.is_empty()
+                && !session.is_user_connected(conn.user_info.sub) {
+                session.stop_typing(session_id, conn.user_info.sub);
+                let left = ServerMessage::ParticipantLeft(Participant::from(&conn.user_info)).to_json();
+                session.fan_out(session_id, &left, None);
+            }
+
+            if session.connections.is_empty()
+                && let Some(mut session) = sessions.remove(&session_id) {
+                session.typing.clear();
+                let idle = IdleSession { emptied_at: Instant::now(), session };
+                self.idle_sessions.lock().unwrap().insert(session_id, idle);
+                println!("🗑️  Session {} empty and set aside", session_id);
             }
         }
     }
 
-    pub fn broadcast_message(&self, session_id: Uuid, message: &str, skip_id: Option<usize>) {
+    /// Assigns a server id, timestamp and the next per-session sequence number
+    /// to a chat message. When `idempotency_key` was already seen from the same
+    /// user within the idempotency window, the original receipt is returned
+    /// instead. Replies quote their parent while it is still buffered.
+    /// Returns `None` if the session has no live connections.
+    pub fn stamp_chat_message(
+        &self,
+        session_id: Uuid,
+        sender: SenderInfo,
+        chat_msg: ChatMessage,
+        idempotency_key: Option<&str>
+    ) -> Option<StampedMessage> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(&session_id)?;
+
+        let window = self.config.idempotency_window;
+        session.idempotency_keys.retain(|_, (seen_at, _)| seen_at.elapsed() < window);
+
+        let key = idempotency_key.map(|key| (sender.id, key.to_string()));
+        if let Some((_, receipt)) = key.as_ref().and_then(|key| session.idempotency_keys.get(key)) {
+            return Some(StampedMessage::Duplicate(receipt.clone()));
+        }
+
+        let quoted = chat_msg.reply_to.and_then(|parent_id| session.quote(parent_id));
+        let mut mentioned = HashSet::new();
+        let mentions = chat_msg.mentions.into_iter()
+            .filter(|user_id| mentioned.insert(*user_id))
+            .collect();
+
+        session.last_seq += 1;
+        let message = BroadcastMessage {
+            id: Uuid::new_v4(),
+            seq: session.last_seq,
+            sent_at: Utc::now(),
+            sender,
+            content: chat_msg.content,
+            reply_to: chat_msg.reply_to,
+            quoted,
+            mentions,
+            links: chat_msg.links,
+            edited_at: None,
+            deleted_at: None,
+            reactions: Vec::new(),
+        };
+
+        if let Some(key) = key {
+            session.idempotency_keys.insert(key, (Instant::now(), DeliveryReceipt::from(&message)));
+        }
+
+        return Some(StampedMessage::Fresh(message));
+    }
+
+    /// Delivers a direct message to every connection of the recipient and to
+    /// the sender's connections other than `skip_id`. The rest of the session
+    /// never sees it. The last `REPORTABLE_DIRECT_MESSAGES` are kept so the
+    /// recipient can report them.
+    pub fn deliver_direct_message(&self, session_id: Uuid, message: &DirectMessage, skip_id: Option<usize>) {
+        let payload = ServerMessage::DirectMessage(message.clone()).to_json();
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return;
+        };
+
+        session.direct_messages.push_back(message.clone());
+        if session.direct_messages.len() > REPORTABLE_DIRECT_MESSAGES {
+            session.direct_messages.pop_front();
+        }
+
+        for (id, conn) in &session.connections {
+            let user_id = conn.user_info.sub;
+            let is_recipient = user_id == message.to_user_id;
+            let is_other_sender_device = user_id == message.sender.id && Some(*id) != skip_id;
+
+            if (is_recipient || is_other_sender_device)
+                && let Err(e) = conn.sender.try_send(payload.clone()) {
+                eprintln!("❌ Failed to send direct message to connection {} in session {}: {:?}", id, session_id, e);
+            }
+        }
+    }
+
+    /// Asks every connection of `user_id` to close with `code`. Each socket
+    /// task then removes its own connection, announcing the departure as usual.
+    /// Returns how many connections were signalled.
+    pub fn disconnect_user(&self, session_id: Uuid, user_id: Uuid, code: CloseCode) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return 0;
+        };
+
+        let mut signalled = 0;
+        for conn in session.connections.values_mut().filter(|conn| conn.user_info.sub == user_id) {
+            if let Some(closer) = conn.closer.take()
+                && closer.send(code).is_ok() {
+                signalled += 1;
+            }
+        }
+
+        println!("🚪 Closing {} connection(s) of user {} in session {} ({:?})", signalled, user_id, session_id, code);
+        return signalled;
+    }
+
+    /// Counts a post by `user_id` against the per-user and per-session token
+    /// buckets and the `slow_mode` interval that applies to the user, if any.
+    /// Returns how long to wait when the post must be refused; refused posts
+    /// use up nothing.
+    pub fn check_rate(&self, session_id: Uuid, user_id: Uuid, slow_mode: Option<Duration>) -> Result<(), Duration> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        if let Some(interval) = slow_mode
+            && let Some(last_posted) = session.last_posted.get(&user_id) {
+            let elapsed = now.saturating_duration_since(*last_posted);
+            if elapsed < interval {
+                return Err(interval - elapsed);
+            }
+        }
+
+        let user_bucket = session.user_buckets.entry(user_id)
+            .or_insert_with(|| TokenBucket::new(self.config.user_rate_limit, now));
+        let session_bucket = session.session_bucket
+            .get_or_insert_with(|| TokenBucket::new(self.config.session_rate_limit, now));
+
+        if let Some(wait) = user_bucket.wait_time(now).max(session_bucket.wait_time(now)) {
+            return Err(wait);
+        }
+
+        user_bucket.take(now);
+        session_bucket.take(now);
+        session.last_posted.insert(user_id, now);
+        return Ok(());
+    }
+
+    /// Counts an edit, reaction or read receipt by `user_id` against its own
+    /// per-user bucket, separate from posting. Returns how long to wait when
+    /// the action must be refused.
+    pub fn check_action_rate(&self, session_id: Uuid, user_id: Uuid) -> Result<(), Duration> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return Ok(());
+        };
+
+        return take_token(&mut session.action_buckets, user_id, self.config.action_rate_limit);
+    }
+
+    /// Counts an abuse report by `user_id`. Reports have their own budget so
+    /// they neither spend posting tokens nor wait out slow mode.
+    pub fn check_report_rate(&self, session_id: Uuid, user_id: Uuid) -> Result<(), Duration> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return Ok(());
+        };
+
+        return take_token(&mut session.report_buckets, user_id, self.config.report_rate_limit);
+    }
+
+    /// Sends `final_message` to every connection, asks each to close with
+    /// `CloseCode::SessionEnded` and drops the session with all its state.
+    /// Returns how many connections were closed.
+    pub fn end_session(&self, session_id: Uuid, final_message: &str) -> usize {
+        self.idle_sessions.lock().unwrap().remove(&session_id);
+        let Some(mut session) = self.sessions.lock().unwrap().remove(&session_id) else {
+            return 0;
+        };
+
+        session.fan_out(session_id, final_message, None);
+        for conn in session.connections.values_mut() {
+            if let Some(closer) = conn.closer.take() {
+                let _ = closer.send(CloseCode::SessionEnded);
+            }
+        }
+
+        println!("🏁 Session {} ended, closing {} connection(s)", session_id, session.connections.len());
+        return session.connections.len();
+    }
+
+    pub fn is_user_connected(&self, session_id: Uuid, user_id: Uuid) -> bool {
         let sessions = self.sessions.lock().unwrap();
-        
-        if let Some(session) = sessions.get(&session_id) {
-            println!("📡 Broadcasting to session {} (skip_id: {:?}). Total recipients: {}", 
-                session_id, skip_id, session.len());
+        return sessions.get(&session_id).is_some_and(|session| session.is_user_connected(user_id));
+    }
 
-            let mut sent_count = 0;
-            let mut failed_count = 0;
+    /// The receipt of a chat message `user_id` already sent with
+    /// `idempotency_key` within the idempotency window.
+    pub fn delivery_receipt(&self, session_id: Uuid, user_id: Uuid, idempotency_key: &str) -> Option<DeliveryReceipt> {
+        let sessions = self.sessions.lock().unwrap();
+        let (seen_at, receipt) = sessions.get(&session_id)?.idempotency_keys.get(&(user_id, idempotency_key.to_string()))?;
 
-            for (id, conn) in session {
-                if skip_id.is_some() && skip_id.unwrap() == *id {
-                    println!("⏭️  Skipping conn_id={} (sender)", id);
-                    continue;
-                }
+        return (seen_at.elapsed() < self.config.idempotency_window).then(|| receipt.clone());
+    }
 
-                println!("📤 Attempting to send to conn_id={} ({})", id, conn.user_info.name);
-                
-                match conn.sender.try_send(message.to_string()) {
-                    Ok(_) => {
-                        sent_count += 1;
-                        println!("✅ Message sent to conn_id={}", id);
-                    },
-                    Err(e) => {
-                        failed_count += 1;
-                        eprintln!("❌ Failed to send message to connection {} ({}): {:?}", 
-                            id, conn.user_info.name, e);
-                    }
-                }
+    /// Releases an idempotency key so a message that failed to publish can be
+    /// retried with the same `client_msg_id`.
+    /// Records `receipt` under `idempotency_key` for a message that is not
+    /// stamped, such as a direct message. Returns the earlier receipt instead
+    /// when the key was already used within the idempotency window.
+    pub fn claim_idempotency_key(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        idempotency_key: &str,
+        receipt: DeliveryReceipt
+    ) -> Option<DeliveryReceipt> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(&session_id)?;
+
+        let window = self.config.idempotency_window;
+        session.idempotency_keys.retain(|_, (seen_at, _)| seen_at.elapsed() < window);
+
+        let key = (user_id, idempotency_key.to_string());
+        if let Some((_, existing)) = session.idempotency_keys.get(&key) {
+            return Some(existing.clone());
+        }
+
+        session.idempotency_keys.insert(key, (Instant::now(), receipt));
+        return None;
+    }
+
+    pub fn forget_idempotency_key(&self, session_id: Uuid, user_id: Uuid, idempotency_key: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        if let Some(session) = sessions.get_mut(&session_id) {
+            session.idempotency_keys.remove(&(user_id, idempotency_key.to_string()));
+        }
+    }
+
+    /// Records a stamped chat message in the session's replay buffer and fans
+    /// it out to every connection except `skip_id`.
+    pub fn broadcast_chat_message(&self, session_id: Uuid, message: BroadcastMessage, skip_id: Option<usize>) {
+        let payload = ServerMessage::ChatMessage(message.clone()).to_json();
+        let mut sessions = self.sessions.lock().unwrap();
+
+        if let Some(session) = sessions.get_mut(&session_id) {
+            session.record_history(message, &self.config);
+            session.fan_out(session_id, &payload, skip_id);
+        } else {
+            eprintln!("❌ Session {} not found for broadcasting", session_id);
+        }
+    }
+
+    /// Updates a user's typing indicator. Only transitions are fanned out to
+    /// the rest of the session: repeated `typing_start` frames just extend the
+    /// indicator's lifetime, and `typing_stop` only shortens it to
+    /// `typing_stop_delay`, leaving the broadcast to `expire_typing`. A start
+    /// within that delay keeps the indicator up, so a client toggling between
+    /// the two produces at most one start and one stop per delay.
+    pub fn set_typing(&self, session_id: Uuid, user: &Claims, is_typing: bool) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return;
+        };
+
+        if !is_typing {
+            if let Some((_, expires_at)) = session.typing.get_mut(&user.sub) {
+                *expires_at = (*expires_at).min(Instant::now() + self.config.typing_stop_delay);
+            }
+            return;
+        }
+
+        let expires_at = Instant::now() + self.config.typing_timeout;
+        if let Some((_, existing)) = session.typing.get_mut(&user.sub) {
+            *existing = expires_at;
+            return;
+        }
+
+        let participant = Participant::from(user);
+        let started = ServerMessage::Typing { participant: participant.clone(), is_typing: true }.to_json();
+        session.typing.insert(user.sub, (participant, expires_at));
+        session.fan_out_except_user(session_id, user.sub, &started);
+    }
+
+    /// Snapshot of the replay buffer for a report against `message_id` or,
+    /// when no message is named, `user_id`. Deleted messages can be reported
+    /// too, although only their tombstone is left. A direct message can only
+    /// be reported by its recipient, `reporter_id`, and comes without
+    /// context. `None` when the message is no longer buffered.
+    pub fn report_context(
+        &self,
+        session_id: Uuid,
+        reporter_id: Uuid,
+        message_id: Option<Uuid>,
+        user_id: Option<Uuid>
+    ) -> Option<ReportContext> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(&session_id);
+        let history: Vec<&BroadcastMessage> = session
+            .map(|session| session.history.iter().map(|(_, message)| message).collect())
+            .unwrap_or_default();
+
+        if let Some(message_id) = message_id {
+            let Some(position) = history.iter().position(|message| message.id == message_id) else {
+                let direct_message = session?.direct_messages.iter()
+                    .find(|message| message.id == message_id && message.to_user_id == reporter_id)?;
+
+                return Some(ReportContext {
+                    message: None,
+                    direct_message: Some(direct_message.clone()),
+                    reported_user_id: direct_message.sender.id,
+                    context: Vec::new(),
+                });
+            };
+            let window = position.saturating_sub(REPORT_CONTEXT_MESSAGES)..(position + REPORT_CONTEXT_MESSAGES + 1).min(history.len());
+
+            return Some(ReportContext {
+                message: Some(history[position].clone()),
+                direct_message: None,
+                reported_user_id: history[position].sender.id,
+                context: history[window].iter()
+                    .filter(|message| message.id != message_id)
+                    .map(|message| (*message).clone())
+                    .collect(),
+            });
+        }
+
+        let user_id = user_id?;
+        let mut context: Vec<BroadcastMessage> = history.iter().rev()
+            .filter(|message| message.sender.id == user_id)
+            .take(REPORT_CONTEXT_MESSAGES * 2)
+            .map(|message| (*message).clone())
+            .collect();
+        context.reverse();
+
+        return Some(ReportContext { message: None, direct_message: None, reported_user_id: user_id, context });
+    }
+
+    /// Checks that `user_id` may edit or delete a buffered message: it must be
+    /// theirs, unless they coach the session.
+    pub fn authorize_message_change(
+        &self,
+        session_id: Uuid,
+        message_id: Uuid,
+        user_id: Uuid,
+        is_coach: bool
+    ) -> Result<(), MessageChangeError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let message = sessions.get_mut(&session_id)
+            .and_then(|session| session.find_message_mut(message_id))
+            .ok_or(MessageChangeError::NotFound)?;
+
+        if message.sender.id != user_id && !is_coach {
+            return Err(MessageChangeError::Forbidden);
+        }
+
+        return Ok(());
+    }
+
+    /// Rewrites a buffered message and broadcasts the update to the whole
+    /// session. Callers authorize first with `authorize_message_change`.
+    pub fn apply_edit(
+        &self,
+        session_id: Uuid,
+        message_id: Uuid,
+        content: SanitizedContent,
+        edited_by: Uuid,
+        edited_at: DateTime<Utc>
+    ) {
+        let SanitizedContent { content, links } = content;
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return;
+        };
+
+        if let Some(message) = session.find_message_mut(message_id) {
+            message.content = content.clone();
+            message.links = links.clone();
+            message.edited_at = Some(edited_at);
+        }
+        session.refresh_quotes(message_id);
+
+        let edited = ServerMessage::MessageEdited { message_id, content, links, edited_at, edited_by }.to_json();
+        session.fan_out(session_id, &edited, None);
+    }
+
+    /// Tombstones a buffered message and broadcasts the deletion to the whole
+    /// session. Callers authorize first with `authorize_message_change`.
+    pub fn apply_delete(&self, session_id: Uuid, message_id: Uuid, deleted_by: Uuid, deleted_at: DateTime<Utc>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return;
+        };
+
+        if let Some(message) = session.find_message_mut(message_id) {
+            message.content.clear();
+            message.links.clear();
+            message.deleted_at = Some(deleted_at);
+            message.reactions.clear();
+        }
+        session.reactions.remove(&message_id);
+        session.refresh_quotes(message_id);
+
+        let deleted = ServerMessage::MessageDeleted { message_id, deleted_at, deleted_by }.to_json();
+        session.fan_out(session_id, &deleted, None);
+    }
+
+    /// Adds or removes `user_id`'s `emoji` reaction on a buffered message and
+    /// broadcasts the new count. Repeating a reaction, or removing one that
+    /// does not exist, is a silent no-op.
+    pub fn react(
+        &self,
+        session_id: Uuid,
+        message_id: Uuid,
+        user_id: Uuid,
+        emoji: &str,
+        added: bool
+    ) -> Result<(), ReactionError> {
+        if emoji.chars().count() > MAX_EMOJI_CHARS || !is_single_emoji(emoji) {
+            return Err(ReactionError::InvalidEmoji);
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(&session_id).ok_or(ReactionError::MessageNotFound)?;
+        if session.find_message_mut(message_id).is_none() {
+            return Err(ReactionError::MessageNotFound);
+        }
+
+        let by_emoji = session.reactions.entry(message_id).or_default();
+        let changed = if added {
+            if !by_emoji.contains_key(emoji) && by_emoji.len() >= self.config.max_reactions_per_message {
+                return Err(ReactionError::LimitReached);
             }
+            by_emoji.entry(emoji.to_string()).or_default().insert(user_id)
+        } else {
+            by_emoji.get_mut(emoji).is_some_and(|users| users.remove(&user_id))
+        };
+
+        if !changed {
+            return Ok(());
+        }
+
+        let count = by_emoji.get(emoji).map_or(0, |users| users.len());
+        if count == 0 {
+            by_emoji.remove(emoji);
+        }
+
+        let summary: Vec<ReactionCount> = by_emoji.iter()
+            .map(|(emoji, users)| ReactionCount { emoji: emoji.clone(), count: users.len() })
+            .collect();
+        if let Some(message) = session.find_message_mut(message_id) {
+            message.reactions = summary;
+        }
+
+        let update = ServerMessage::ReactionUpdated {
+            message_id,
+            emoji: emoji.to_string(),
+            user_id,
+            added,
+            count
+        }.to_json();
+        session.fan_out(session_id, &update, None);
+
+        return Ok(());
+    }
+
+    /// Advances a user's read high-water mark and broadcasts the receipt to the
+    /// session. `seq` is clamped to the latest message; marks never move
+    /// backwards. Returns the new mark, or `None` if nothing changed.
+    pub fn mark_read(&self, session_id: Uuid, user: &Claims, seq: u64) -> Option<u64> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(&session_id)?;
+
+        let seq = seq.min(session.last_seq);
+        let current = session.read_marks.get(&user.sub).copied().unwrap_or(0);
+        if seq <= current {
+            return None;
+        }
+
+        session.read_marks.insert(user.sub, seq);
+        let receipt = ServerMessage::ReadReceipt { participant: Participant::from(user), seq }.to_json();
+        session.fan_out(session_id, &receipt, None);
+
+        return Some(seq);
+    }
 
-            println!("📊 Broadcast summary: {} sent, {} failed", sent_count, failed_count);
+    /// Clears typing indicators whose clients stopped refreshing them.
+    pub fn expire_typing(&self) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+
+        for (session_id, session) in sessions.iter_mut() {
+            let expired: Vec<Uuid> = session.typing.iter()
+                .filter(|(_, (_, expires_at))| *expires_at <= now)
+                .map(|(user_id, _)| *user_id)
+                .collect();
+
+            for user_id in expired {
+                session.stop_typing(*session_id, user_id);
+            }
+        }
+    }
+
+    /// Drops idle sessions kept longer than `idle_retention` and frees the
+    /// history and idempotency keys of those past `idempotency_window`.
+    /// Returns how many were dropped.
+    pub fn expire_idle_sessions(&self) -> usize {
+        let mut idle_sessions = self.idle_sessions.lock().unwrap();
+        let before = idle_sessions.len();
+
+        idle_sessions.retain(|_, idle| idle.emptied_at.elapsed() < self.config.idle_retention);
+        for idle in idle_sessions.values_mut() {
+            idle.shrink(self.config.idempotency_window);
+        }
+
+        return before - idle_sessions.len();
+    }
+
+    pub fn broadcast_message(&self, session_id: Uuid, message: &str, skip_id: Option<usize>) {
+        let sessions = self.sessions.lock().unwrap();
+
+        if let Some(session) = sessions.get(&session_id) {
+            session.fan_out(session_id, message, skip_id);
         } else {
             eprintln!("❌ Session {} not found for broadcasting", session_id);
         }
@@ -84,7 +1026,7 @@ impl SessionManager {
     pub fn get_user_info(&self, session_id: Uuid, conn_id: usize) -> Option<Claims> {
         let sessions = self.sessions.lock().unwrap();
         return sessions.get(&session_id)
-            .and_then(|session| session.get(&conn_id))
+            .and_then(|session| session.connections.get(&conn_id))
             .map(|conn| conn.user_info.clone());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,307 @@
+use crate::config::env_secs;
+use crate::model::protocol::{ChatMode, ServerMessage};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Who may post and how often, as set by the coach.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatSettings {
+    pub mode: ChatMode,
+    /// Users besides the coach who may post in announcement-only mode.
+    pub co_hosts: BTreeSet<Uuid>,
+    /// Minimum time between two posts of the same user.
+    pub slow_mode: Option<Duration>
+}
+
+impl ChatSettings {
+    /// Open chat without co-hosts or slow mode, which clients assume until
+    /// told otherwise.
+    pub fn is_default(&self) -> bool {
+        return *self == ChatSettings::default();
+    }
+
+    pub fn to_message(&self) -> ServerMessage {
+        return ServerMessage::ChatSettings {
+            mode: self.mode,
+            co_hosts: self.co_hosts.iter().copied().collect(),
+            slow_mode_secs: self.slow_mode.map(|interval| interval.as_secs()),
+        };
+    }
+}
+
+/// What realtime-service knows about a session from auth-service's NATS
+/// events. Unlike `SessionManager` state, entries outlive the connections.
+#[derive(Debug, Clone, Default)]
+pub struct SessionInfo {
+    pub coach_id: Option<Uuid>,
+    pub start_at: Option<DateTime<Utc>>,
+    /// Users who joined through auth-service, not counting the coach.
+    pub participants: HashSet<Uuid>,
+    /// Users who may not post until the given time.
+    pub muted: HashMap<Uuid, DateTime<Utc>>,
+    /// Users refused for the rest of the session.
+    pub banned: HashSet<Uuid>,
+    pub chat_settings: ChatSettings,
+    /// Set by `session.created`; every later join arrives as an event.
+    pub created: bool,
+    /// Set by `session.started`; opens the session regardless of `start_at`.
+    pub started: bool,
+    /// Set by `session.ended` or `session.cancelled`; no connections are
+    /// admitted afterwards.
+    pub ended: bool,
+    /// When the entry was first recorded.
+    pub recorded_at: Option<DateTime<Utc>>,
+    /// When `session.ended` or `session.cancelled` arrived.
+    pub ended_at: Option<DateTime<Utc>>
+}
+
+impl SessionInfo {
+    /// The time `retention` counts from: the end if known, otherwise the
+    /// later of the scheduled start and when the entry was recorded.
+    fn retained_since(&self) -> Option<DateTime<Utc>> {
+        return self.ended_at.or(self.start_at.max(self.recorded_at));
+    }
+}
+
+pub struct SessionRegistry {
+    sessions: Mutex<HashMap<Uuid, SessionInfo>>,
+    /// How long before `start_at` connections are admitted.
+    early_admission: Duration,
+    /// How long an entry is kept after its session ended, or after its
+    /// start if no end arrives (`SESSION_RETENTION_SECS`). Once evicted, an
+    /// ended session is no longer refused by the registry alone.
+    retention: Duration
+}
+
+const DEFAULT_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+impl Default for SessionRegistry {
+    fn default() -> Self {
+        return SessionRegistry::new();
+    }
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::with_timing(
+            env_secs("SESSION_EARLY_ADMISSION_SECS", 15 * 60),
+            env_secs("SESSION_RETENTION_SECS", DEFAULT_RETENTION.as_secs())
+        )
+    }
+
+    pub fn with_early_admission(early_admission: Duration) -> Self {
+        Self::with_timing(early_admission, DEFAULT_RETENTION)
+    }
+
+    pub fn with_timing(early_admission: Duration, retention: Duration) -> Self {
+        SessionRegistry {
+            sessions: Mutex::new(HashMap::new()),
+            early_admission,
+            retention
+        }
+    }
+
+    pub fn record_created(&self, session_id: Uuid, coach_id: Uuid) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let info = entry(&mut sessions, session_id);
+        info.coach_id = Some(coach_id);
+        info.created = true;
+        println!("📒 Session {} registered with coach {}", session_id, coach_id);
+    }
+
+    /// Records the coach as reported by auth-service, without treating the
+    /// participant list as complete the way `record_created` does.
+    pub fn record_coach(&self, session_id: Uuid, coach_id: Uuid) {
+        let mut sessions = self.sessions.lock().unwrap();
+        entry(&mut sessions, session_id).coach_id = Some(coach_id);
+        println!("📒 Session {} has coach {} according to auth-service", session_id, coach_id);
+    }
+
+    pub fn record_schedule(&self, session_id: Uuid, start_at: DateTime<Utc>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        entry(&mut sessions, session_id).start_at = Some(start_at);
+        println!("📒 Session {} scheduled to start at {}", session_id, start_at);
+    }
+
+    pub fn record_started(&self, session_id: Uuid) {
+        let mut sessions = self.sessions.lock().unwrap();
+        entry(&mut sessions, session_id).started = true;
+        println!("📒 Session {} started", session_id);
+    }
+
+    /// Marks the session as over. Only the flag is kept, so later upgrades
+    /// can still be refused.
+    pub fn record_ended(&self, session_id: Uuid) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let ended_at = Utc::now();
+        let info = SessionInfo { ended: true, recorded_at: Some(ended_at), ended_at: Some(ended_at), ..SessionInfo::default() };
+        sessions.insert(session_id, info);
+        println!("📒 Session {} ended", session_id);
+    }
+
+    pub fn is_ended(&self, session_id: Uuid) -> bool {
+        let sessions = self.sessions.lock().unwrap();
+        return sessions.get(&session_id).is_some_and(|info| info.ended);
+    }
+
+    /// The session's start time if connections are not admitted yet at `now`.
+    /// Sessions without a known schedule, or already started, are open.
+    pub fn start_pending(&self, session_id: Uuid, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let sessions = self.sessions.lock().unwrap();
+        let info = sessions.get(&session_id).filter(|info| !info.started)?;
+        let start_at = info.start_at?;
+        let early = chrono::Duration::from_std(self.early_admission).unwrap_or(chrono::Duration::MAX);
+
+        let opens_at = start_at.checked_sub_signed(early);
+
+        return opens_at.is_some_and(|opens_at| now < opens_at).then_some(start_at);
+    }
+
+    pub fn record_joined(&self, session_id: Uuid, user_id: Uuid) {
+        let mut sessions = self.sessions.lock().unwrap();
+        entry(&mut sessions, session_id).participants.insert(user_id);
+        println!("📒 User {} joined session {}", user_id, session_id);
+    }
+
+    /// Whether `session.created` has been seen for `session_id`, so every
+    /// later join arrives as an event. Before that, a missing member may
+    /// only mean the events were missed, e.g. across a restart.
+    pub fn is_known(&self, session_id: Uuid) -> bool {
+        let sessions = self.sessions.lock().unwrap();
+        return sessions.get(&session_id).is_some_and(|info| info.created);
+    }
+
+    /// Whether `user_id` is the coach of, or has joined, `session_id`.
+    pub fn is_member(&self, session_id: Uuid, user_id: Uuid) -> bool {
+        let sessions = self.sessions.lock().unwrap();
+        return sessions.get(&session_id)
+            .is_some_and(|info| info.coach_id == Some(user_id) || info.participants.contains(&user_id));
+    }
+
+    pub fn is_coach(&self, session_id: Uuid, user_id: Uuid) -> bool {
+        let sessions = self.sessions.lock().unwrap();
+        return sessions.get(&session_id)
+            .and_then(|info| info.coach_id)
+            .is_some_and(|coach_id| coach_id == user_id);
+    }
+
+    pub fn mute(&self, session_id: Uuid, user_id: Uuid, until: DateTime<Utc>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        entry(&mut sessions, session_id).muted.insert(user_id, until);
+        println!("🔇 User {} muted in session {} until {}", user_id, session_id, until);
+    }
+
+    pub fn unmute(&self, session_id: Uuid, user_id: Uuid) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(info) = sessions.get_mut(&session_id) {
+            info.muted.remove(&user_id);
+        }
+    }
+
+    /// End of `user_id`'s mute, if one is still running.
+    pub fn muted_until(&self, session_id: Uuid, user_id: Uuid) -> Option<DateTime<Utc>> {
+        let sessions = self.sessions.lock().unwrap();
+        return sessions.get(&session_id)
+            .and_then(|info| info.muted.get(&user_id).copied())
+            .filter(|until| *until > Utc::now());
+    }
+
+    pub fn ban(&self, session_id: Uuid, user_id: Uuid) {
+        let mut sessions = self.sessions.lock().unwrap();
+        entry(&mut sessions, session_id).banned.insert(user_id);
+        println!("⛔ User {} banned from session {}", user_id, session_id);
+    }
+
+    pub fn unban(&self, session_id: Uuid, user_id: Uuid) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(info) = sessions.get_mut(&session_id) {
+            info.banned.remove(&user_id);
+        }
+    }
+
+    pub fn is_banned(&self, session_id: Uuid, user_id: Uuid) -> bool {
+        let sessions = self.sessions.lock().unwrap();
+        return sessions.get(&session_id).is_some_and(|info| info.banned.contains(&user_id));
+    }
+
+    pub fn chat_settings(&self, session_id: Uuid) -> ChatSettings {
+        let sessions = self.sessions.lock().unwrap();
+        return sessions.get(&session_id).map(|info| info.chat_settings.clone()).unwrap_or_default();
+    }
+
+    /// Switches the chat mode and returns the resulting settings.
+    pub fn set_chat_mode(&self, session_id: Uuid, mode: ChatMode) -> ChatSettings {
+        let mut sessions = self.sessions.lock().unwrap();
+        let settings = &mut entry(&mut sessions, session_id).chat_settings;
+        settings.mode = mode;
+        println!("💬 Chat mode of session {} set to {:?}", session_id, mode);
+        return settings.clone();
+    }
+
+    /// Grants or revokes co-host rights. Returns the resulting settings, or
+    /// `None` when nothing changed.
+    pub fn set_co_host(&self, session_id: Uuid, user_id: Uuid, co_host: bool) -> Option<ChatSettings> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let settings = &mut entry(&mut sessions, session_id).chat_settings;
+        let changed = if co_host { settings.co_hosts.insert(user_id) } else { settings.co_hosts.remove(&user_id) };
+        return changed.then(|| settings.clone());
+    }
+
+    /// Sets or, with `None` or a zero interval, lifts slow mode and returns
+    /// the resulting settings.
+    pub fn set_slow_mode(&self, session_id: Uuid, interval: Option<Duration>) -> ChatSettings {
+        let mut sessions = self.sessions.lock().unwrap();
+        let settings = &mut entry(&mut sessions, session_id).chat_settings;
+        settings.slow_mode = interval.filter(|interval| !interval.is_zero());
+        println!("🐢 Slow mode of session {} set to {:?}", session_id, settings.slow_mode);
+        return settings.clone();
+    }
+
+    /// The chat mode that keeps `user_id` from posting, if any. The coach and
+    /// co-hosts may post in announcement-only mode; nobody may when disabled.
+    pub fn post_restriction(&self, session_id: Uuid, user_id: Uuid, is_coach: bool) -> Option<ChatMode> {
+        let sessions = self.sessions.lock().unwrap();
+        let settings = &sessions.get(&session_id)?.chat_settings;
+
+        return match settings.mode {
+            ChatMode::Open => None,
+            ChatMode::AnnouncementOnly if is_coach || settings.co_hosts.contains(&user_id) => None,
+            mode => Some(mode),
+        };
+    }
+
+    /// The slow mode interval `user_id` has to respect; the coach and
+    /// co-hosts are exempt.
+    pub fn slow_mode_for(&self, session_id: Uuid, user_id: Uuid, is_coach: bool) -> Option<Duration> {
+        let sessions = self.sessions.lock().unwrap();
+        let settings = &sessions.get(&session_id)?.chat_settings;
+
+        if is_coach || settings.co_hosts.contains(&user_id) {
+            return None;
+        }
+        return settings.slow_mode;
+    }
+    /// Drops entries whose `retention` ran out at `now` and returns how
+    /// many were dropped.
+    pub fn evict_stale(&self, now: DateTime<Utc>) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let retention = chrono::Duration::from_std(self.retention).unwrap_or(chrono::Duration::MAX);
+        let before = sessions.len();
+
+        sessions.retain(|_, info| {
+            let expires_at = info.retained_since().and_then(|since| since.checked_add_signed(retention));
+            return expires_at.is_none_or(|expires_at| now < expires_at);
+        });
+
+        return before - sessions.len();
+    }
+}
+
+/// The entry for `session_id`, stamped with the current time when new.
+fn entry(sessions: &mut HashMap<Uuid, SessionInfo>, session_id: Uuid) -> &mut SessionInfo {
+    return sessions.entry(session_id)
+        .or_insert_with(|| SessionInfo { recorded_at: Some(Utc::now()), ..SessionInfo::default() });
+}
\ No newline at end of file
@@ -0,0 +1,28 @@
+use actix_web::web;
+use chrono::Utc;
+use std::time::Duration;
+use tokio::time::interval;
+
+use crate::services::session_manager::SessionManager;
+use crate::services::session_registry::SessionRegistry;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically drops idle sessions and registry entries of sessions long
+/// over, so neither grows with every session ever held.
+pub async fn run_state_eviction(manager: web::Data<SessionManager>, registry: web::Data<SessionRegistry>) {
+    let mut ticker = interval(SWEEP_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+        let dropped = manager.expire_idle_sessions();
+        if dropped > 0 {
+            println!("🧹 Dropped {} idle sessions", dropped);
+        }
+
+        let evicted = registry.evict_stale(Utc::now());
+        if evicted > 0 {
+            println!("🧹 Evicted {} stale sessions from the registry", evicted);
+        }
+    }
+}
\ No newline at end of file
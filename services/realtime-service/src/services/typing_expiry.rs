@@ -0,0 +1,17 @@
+use actix_web::web;
+use std::time::Duration;
+use tokio::time::interval;
+
+use crate::services::session_manager::SessionManager;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Periodically clears typing indicators that clients stopped refreshing.
+pub async fn run_typing_expiry(manager: web::Data<SessionManager>) {
+    let mut ticker = interval(SWEEP_INTERVAL);
+
+    loop {
+        ticker.tick().await;
+        manager.expire_typing();
+    }
+}
\ No newline at end of file
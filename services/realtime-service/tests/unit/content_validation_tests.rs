@@ -0,0 +1,69 @@
+use realtime_service::services::content_validation::{ContentError, ContentLimits};
+
+fn limits(max_content_chars: usize) -> ContentLimits {
+    ContentLimits { max_content_chars, ..ContentLimits::default() }
+}
+
+#[cfg(test)]
+mod validate_content_tests {
+    use super::*;
+
+    #[test]
+    fn test_trims_surrounding_whitespace() {
+        assert_eq!(limits(100).validate_content("  hello\n").unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_rejects_empty_and_whitespace_only() {
+        assert_eq!(limits(100).validate_content(""), Err(ContentError::Empty));
+        assert_eq!(limits(100).validate_content(" \n\t "), Err(ContentError::Empty));
+    }
+
+    #[test]
+    fn test_length_is_counted_in_characters() {
+        let limits = limits(5);
+
+        assert!(limits.validate_content("héllo").is_ok(), "Multi-byte characters count once");
+        assert!(limits.validate_content("👍👍👍👍👍").is_ok());
+        assert_eq!(limits.validate_content("hello!"), Err(ContentError::TooLong { max_chars: 5 }));
+    }
+
+    #[test]
+    fn test_rejects_control_characters() {
+        assert_eq!(limits(100).validate_content("bell\u{7}"), Err(ContentError::ControlCharacter));
+        assert_eq!(limits(100).validate_content("nul\0byte"), Err(ContentError::ControlCharacter));
+    }
+
+    #[test]
+    fn test_keeps_newlines_and_tabs() {
+        assert_eq!(limits(100).validate_content("a\r\n\tb").unwrap(), "a\n\tb");
+    }
+
+    #[test]
+    fn test_normalizes_to_nfc() {
+        let decomposed = "e\u{301}";
+
+        assert_eq!(limits(100).validate_content(decomposed).unwrap(), "\u{e9}");
+        assert!(limits(1).validate_content(decomposed).is_ok(), "Length is measured after normalization");
+    }
+}
+
+#[cfg(test)]
+mod sanitize_tests {
+    use super::*;
+
+    #[test]
+    fn test_strips_markup_before_validating() {
+        let sanitized = limits(100).sanitize("  <p>hi https://example.com</p> ").unwrap();
+
+        assert_eq!(sanitized.content, "hi https://example.com");
+        assert_eq!(sanitized.links.len(), 1);
+        assert_eq!(sanitized.links[0].start, 3);
+        assert_eq!(limits(100).sanitize("<b></b>"), Err(ContentError::Empty));
+    }
+
+    #[test]
+    fn test_rejects_unsafe_links() {
+        assert!(matches!(limits(100).sanitize("javascript:void(0)"), Err(ContentError::UnsafeLink { .. })));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,11 @@
+//! Unit test harness. Each file is one module of the `unit` test target.
+
+mod content_validation_tests;
+mod membership_tests;
+mod message_filter_tests;
+mod protocol_tests;
+mod rate_limiter_tests;
+mod sanitizer_tests;
+mod session_registry_tests;
+mod session_state_tests;
+mod ws_route_tests;
\ No newline at end of file
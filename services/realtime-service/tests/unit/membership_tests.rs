@@ -0,0 +1,76 @@
+use realtime_service::services::membership::MembershipLookup;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{header, method, path};
+use serde_json::json;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn lookup_for(server: &MockServer) -> MembershipLookup {
+    let template = format!("{}/internal/sessions/{{session_id}}/members/{{user_id}}", server.uri());
+    MembershipLookup::new(Some(template), Some("s3cret".to_string()), Duration::from_secs(2))
+}
+
+#[cfg(test)]
+mod membership_lookup_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disabled_lookup_denies() {
+        let lookup = MembershipLookup::new(None, None, Duration::from_secs(1));
+
+        assert!(!lookup.is_enabled());
+        assert!(lookup.lookup(Uuid::new_v4(), Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_member_is_confirmed_with_internal_secret() {
+        let server = MockServer::start().await;
+        let (session_id, user_id, coach_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        Mock::given(method("GET"))
+            .and(path(format!("/internal/sessions/{}/members/{}", session_id, user_id)))
+            .and(header("X-Internal-Secret", "s3cret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "session_id": session_id,
+                "user_id": user_id,
+                "coach_id": coach_id
+            })))
+            .mount(&server)
+            .await;
+
+        let membership = lookup_for(&server).lookup(session_id, user_id).await.unwrap();
+        assert_eq!(membership.map(|m| m.coach_id), Some(coach_id));
+    }
+
+    #[tokio::test]
+    async fn test_not_found_means_not_a_member() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        assert!(lookup_for(&server).lookup(Uuid::new_v4(), Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_secret_is_reported() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        assert!(lookup_for(&server).lookup(Uuid::new_v4(), Uuid::new_v4()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_error_is_reported() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        assert!(lookup_for(&server).lookup(Uuid::new_v4(), Uuid::new_v4()).await.is_err());
+    }
+}
\ No newline at end of file
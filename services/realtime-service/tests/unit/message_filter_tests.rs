@@ -0,0 +1,139 @@
+use realtime_service::services::content_validation::SanitizedContent;
+use realtime_service::services::message_filter::{
+    FilterAction, FilterOutcome, FilterPipeline, FilterVerdict, MessageFilter, RegexFilter, WordListFilter,
+};
+use regex::Regex;
+use std::io::Write;
+
+fn text(content: &str) -> SanitizedContent {
+    SanitizedContent { content: content.to_string(), links: Vec::new() }
+}
+
+fn email_filter(action: FilterAction) -> Box<dyn MessageFilter> {
+    Box::new(RegexFilter::new("email", Regex::new(r"[\w.+-]+@[\w-]+(\.[\w-]+)+").unwrap(), action))
+}
+
+fn tempfile() -> (String, std::fs::File) {
+    let path = std::env::temp_dir().join(format!("message-filter-{}.txt", uuid::Uuid::new_v4()));
+    let file = std::fs::File::create(&path).unwrap();
+    (path.to_string_lossy().into_owned(), file)
+}
+
+#[cfg(test)]
+mod word_list_tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_whole_words_case_insensitively() {
+        let filter = WordListFilter::new(["darn"], FilterAction::Mask);
+
+        assert_eq!(filter.check("Darn it, darnation!"), FilterOutcome::Mask("**** it, darnation!".to_string()));
+        assert_eq!(filter.check("all good"), FilterOutcome::Allow);
+    }
+
+    #[test]
+    fn test_flag_reports_matched_words() {
+        let filter = WordListFilter::new(["spam"], FilterAction::Flag);
+
+        match filter.check("buy spam now") {
+            FilterOutcome::Flag { reason } => assert!(reason.contains("spam")),
+            other => panic!("Expected flag, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_skips_comments_and_blank_lines() {
+        let mut file = tempfile();
+        writeln!(file.1, "# profanity\n\nheck\n  Drat  ").unwrap();
+
+        let filter = WordListFilter::load(&file.0, FilterAction::Reject).unwrap();
+
+        assert!(matches!(filter.check("oh DRAT"), FilterOutcome::Reject { .. }));
+        assert_eq!(filter.check("# profanity"), FilterOutcome::Allow);
+        std::fs::remove_file(&file.0).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod regex_filter_tests {
+    use super::*;
+
+    #[test]
+    fn test_load_reads_rules_in_order() {
+        let mut file = tempfile();
+        writeln!(file.1, "# action name pattern\nreject phone \\+?\\d[\\d ().-]{{7,}}\\d\nmask  email   [\\w.+-]+@[\\w-]+(\\.[\\w-]+)+").unwrap();
+
+        let filters = RegexFilter::load(&file.0).unwrap();
+
+        assert_eq!(filters.iter().map(|f| f.name()).collect::<Vec<_>>(), ["phone", "email"]);
+        assert!(matches!(filters[0].check("call +1 555 123 4567"), FilterOutcome::Reject { .. }));
+        assert_eq!(filters[1].check("a@b.co"), FilterOutcome::Mask("******".to_string()));
+        std::fs::remove_file(&file.0).unwrap();
+    }
+
+    #[test]
+    fn test_load_reports_bad_rules_with_line_number() {
+        let mut file = tempfile();
+        writeln!(file.1, "mask ok x\nban nope y").unwrap();
+
+        let error = RegexFilter::load(&file.0).err().expect("Unknown actions should fail");
+
+        assert!(error.to_string().contains(":2:"), "{}", error);
+        std::fs::remove_file(&file.0).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod pipeline_tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_and_flags_accumulate() {
+        let pipeline = FilterPipeline::new(vec![
+            Box::new(WordListFilter::new(["heck"], FilterAction::Mask)),
+            email_filter(FilterAction::Flag),
+        ]);
+
+        match pipeline.apply(text("heck, mail me at me@example.com")) {
+            FilterVerdict::Deliver { content, flags } => {
+                assert_eq!(content.content, "****, mail me at me@example.com");
+                assert_eq!(flags.len(), 1);
+                assert_eq!(flags[0].filter, "email");
+            }
+            other => panic!("Expected delivery, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reject_stops_the_pipeline() {
+        let pipeline = FilterPipeline::new(vec![
+            email_filter(FilterAction::Reject),
+            Box::new(WordListFilter::new(["me"], FilterAction::Flag)),
+        ]);
+
+        assert_eq!(
+            pipeline.apply(text("me@example.com")),
+            FilterVerdict::Reject { filter: "email".to_string(), reason: "Message blocked by the email filter".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_links_are_recomputed_after_masking() {
+        let pipeline = FilterPipeline::new(vec![Box::new(WordListFilter::new(["heck"], FilterAction::Mask))]);
+        let content = SanitizedContent {
+            content: "heck https://example.com".to_string(),
+            links: Vec::new(),
+        };
+
+        match pipeline.apply(content) {
+            FilterVerdict::Deliver { content, .. } => assert_eq!(content.links[0].start, 5),
+            other => panic!("Expected delivery, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_pipeline_allows_everything() {
+        let verdict = FilterPipeline::new(Vec::new()).apply(text("anything"));
+        assert_eq!(verdict, FilterVerdict::Deliver { content: text("anything"), flags: Vec::new() });
+    }
+}
\ No newline at end of file
@@ -0,0 +1,318 @@
+use realtime_service::model::chat_message::{BroadcastMessage, SenderInfo};
+use realtime_service::model::protocol::{
+    ChatMode, ClientEnvelope, ClientMessage, CloseCode, ErrorCode, ServerMessage, SessionEndReason, PROTOCOL_VERSION,
+};
+use chrono::Utc;
+use serde_json::Value;
+use uuid::Uuid;
+
+#[cfg(test)]
+mod client_envelope_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tagged_chat_message() {
+        let envelope = ClientEnvelope::parse(r#"{"v":1,"type":"chat","content":"Hello"}"#)
+            .expect("Failed to parse chat envelope");
+
+        assert_eq!(envelope.v, 1);
+        match envelope.message {
+            ClientMessage::Chat(chat_msg) => assert_eq!(chat_msg.content, "Hello"),
+            other => panic!("Expected chat message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_defaults_version_when_missing() {
+        let envelope = ClientEnvelope::parse(r#"{"type":"ping"}"#).expect("Failed to parse ping");
+
+        assert_eq!(envelope.v, PROTOCOL_VERSION);
+        assert!(matches!(envelope.message, ClientMessage::Ping {}));
+    }
+
+    #[test]
+    fn test_parse_legacy_untagged_chat_message() {
+        let envelope = ClientEnvelope::parse(r#"{"content":"Legacy client"}"#)
+            .expect("Legacy frames should still be accepted");
+
+        match envelope.message {
+            ClientMessage::Chat(chat_msg) => assert_eq!(chat_msg.content, "Legacy client"),
+            other => panic!("Expected chat message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_chat_reply() {
+        let parent_id = Uuid::new_v4();
+        let text = format!(r#"{{"type":"chat","content":"Yes","reply_to":"{}"}}"#, parent_id);
+
+        match ClientEnvelope::parse(&text).unwrap().message {
+            ClientMessage::Chat(chat_msg) => assert_eq!(chat_msg.reply_to, Some(parent_id)),
+            other => panic!("Expected chat message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_direct_message() {
+        let to_user_id = Uuid::new_v4();
+        let text = format!(r#"{{"type":"direct_message","to_user_id":"{}","content":"psst"}}"#, to_user_id);
+
+        match ClientEnvelope::parse(&text).unwrap().message {
+            ClientMessage::DirectMessage { to_user_id: parsed, content } => {
+                assert_eq!(parsed, to_user_id);
+                assert_eq!(content, "psst");
+            }
+            other => panic!("Expected direct message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_moderation_operations() {
+        let user_id = Uuid::new_v4();
+        let text = format!(r#"{{"type":"mute_user","user_id":"{}","duration_secs":300}}"#, user_id);
+
+        match ClientEnvelope::parse(&text).unwrap().message {
+            ClientMessage::MuteUser { user_id: parsed, duration_secs } => {
+                assert_eq!(parsed, user_id);
+                assert_eq!(duration_secs, 300);
+            }
+            other => panic!("Expected mute, got {:?}", other),
+        }
+
+        let text = format!(r#"{{"type":"kick_user","user_id":"{}"}}"#, user_id);
+        assert!(matches!(ClientEnvelope::parse(&text).unwrap().message, ClientMessage::KickUser { .. }));
+    }
+
+    #[test]
+    fn test_parse_set_chat_mode() {
+        let envelope = ClientEnvelope::parse(r#"{"type":"set_chat_mode","mode":"announcement_only"}"#).unwrap();
+
+        match envelope.message {
+            ClientMessage::SetChatMode { mode } => assert_eq!(mode, ChatMode::AnnouncementOnly),
+            other => panic!("Expected chat mode change, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_report_with_either_target() {
+        let message_id = Uuid::new_v4();
+        let text = format!(r#"{{"type":"report","message_id":"{}","reason":"harassment"}}"#, message_id);
+
+        match ClientEnvelope::parse(&text).unwrap().message {
+            ClientMessage::Report { message_id: parsed, user_id, reason } => {
+                assert_eq!(parsed, Some(message_id));
+                assert_eq!(user_id, None);
+                assert_eq!(reason, "harassment");
+            }
+            other => panic!("Expected report, got {:?}", other),
+        }
+
+        let text = format!(r#"{{"type":"report","user_id":"{}","reason":"spam"}}"#, Uuid::new_v4());
+        assert!(matches!(ClientEnvelope::parse(&text).unwrap().message, ClientMessage::Report { message_id: None, .. }));
+    }
+
+    #[test]
+    fn test_malformed_typed_frames_do_not_fall_back_to_chat() {
+        let malformed_dm = r#"{"type":"direct_message","to_user_id":"nope","content":"secret"}"#;
+        let typo = r#"{"type":"direct_mesage","client_msg_id":"c-1","content":"secret"}"#;
+
+        assert!(ClientEnvelope::parse(malformed_dm).is_err(), "A broken DM must not become a public post");
+        assert!(ClientEnvelope::parse(typo).is_err());
+        assert_eq!(ClientEnvelope::extract_client_msg_id(typo).as_deref(), Some("c-1"));
+    }
+
+    #[test]
+    fn test_parse_unknown_type_fails() {
+        let result = ClientEnvelope::parse(r#"{"type":"self_destruct"}"#);
+        assert!(result.is_err(), "Unknown message types should be rejected");
+    }
+
+    #[test]
+    fn test_parse_reads_client_msg_id() {
+        let envelope = ClientEnvelope::parse(r#"{"type":"chat","client_msg_id":"c-1","content":"Hi"}"#).unwrap();
+        assert_eq!(envelope.client_msg_id.as_deref(), Some("c-1"));
+    }
+
+    #[test]
+    fn test_extract_client_msg_id_from_invalid_message() {
+        let text = r#"{"type":"self_destruct","client_msg_id":"c-42"}"#;
+
+        assert!(ClientEnvelope::parse(text).is_err());
+        assert_eq!(ClientEnvelope::extract_client_msg_id(text).as_deref(), Some("c-42"));
+        assert_eq!(ClientEnvelope::extract_client_msg_id("not json"), None);
+    }
+
+    #[test]
+    fn test_parse_malformed_json_fails() {
+        let result = ClientEnvelope::parse(r#"{"type":"chat","content":"unclosed"#);
+        assert!(result.is_err());
+    }
+}
+
+#[cfg(test)]
+mod server_message_tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_message_serialization() {
+        let sender_id = Uuid::new_v4();
+        let message_id = Uuid::new_v4();
+        let msg = ServerMessage::ChatMessage(BroadcastMessage {
+            id: message_id,
+            seq: 3,
+            sent_at: Utc::now(),
+            sender: SenderInfo { id: sender_id, name: "Alice".to_string() },
+            content: "Hi".to_string(),
+            reply_to: None,
+            quoted: None,
+            mentions: Vec::new(),
+            links: Vec::new(),
+            edited_at: None,
+            deleted_at: None,
+            reactions: Vec::new(),
+        });
+
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert_eq!(json["v"], PROTOCOL_VERSION);
+        assert_eq!(json["type"], "chat_message");
+        assert_eq!(json["id"], message_id.to_string());
+        assert_eq!(json["seq"], 3);
+        assert!(json["sent_at"].is_string());
+        assert!(json.get("deleted_at").is_none(), "Live messages carry no tombstone");
+        assert!(json.get("reply_to").is_none(), "Top-level messages carry no thread fields");
+        assert_eq!(json["sender"]["id"], sender_id.to_string());
+        assert_eq!(json["content"], "Hi");
+    }
+
+    #[test]
+    fn test_session_joined_serialization() {
+        let session_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        let msg = ServerMessage::SessionJoined { session_id, user_id };
+
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert_eq!(json["type"], "session_joined");
+        assert_eq!(json["session_id"], session_id.to_string());
+        assert_eq!(json["user_id"], user_id.to_string());
+    }
+
+    #[test]
+    fn test_session_ended_serialization() {
+        let msg = ServerMessage::SessionEnded { session_id: Uuid::new_v4(), reason: SessionEndReason::Cancelled };
+
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert_eq!(json["type"], "session_ended");
+        assert_eq!(json["reason"], "cancelled");
+    }
+
+    #[test]
+    fn test_server_message_roundtrip() {
+        let msg = ServerMessage::Pong {};
+        let parsed: ServerMessage = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert!(matches!(parsed, ServerMessage::Pong {}));
+    }
+}
+
+#[cfg(test)]
+mod error_frame_tests {
+    use super::*;
+
+    #[test]
+    fn test_error_frame_serialization() {
+        let msg = ServerMessage::error(ErrorCode::InvalidMessage, "bad frame", Some("c-7".to_string()));
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert_eq!(json["type"], "error");
+        assert_eq!(json["code"], "invalid_message");
+        assert_eq!(json["message"], "bad frame");
+        assert_eq!(json["client_msg_id"], "c-7");
+    }
+
+    #[test]
+    fn test_error_frame_omits_missing_client_msg_id() {
+        let msg = ServerMessage::error(ErrorCode::UnsupportedFrame, "binary", None);
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert!(json.get("client_msg_id").is_none());
+    }
+
+    #[test]
+    fn test_rate_limited_frame_carries_retry_after() {
+        let msg = ServerMessage::rate_limited(std::time::Duration::from_millis(1500), Some("c-3".to_string()));
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert_eq!(json["type"], "nack");
+        assert_eq!(json["code"], "rate_limited");
+        assert_eq!(json["client_msg_id"], "c-3");
+        assert!(json["retry_after_ms"].as_u64().unwrap() >= 1500);
+        assert!(!ServerMessage::nack(ErrorCode::InvalidMessage, "x", None).to_json().contains("retry_after_ms"));
+    }
+
+    #[test]
+    fn test_close_codes_are_in_private_range() {
+        let codes = [
+            CloseCode::ProtocolViolation,
+            CloseCode::TokenExpired,
+            CloseCode::Kicked,
+            CloseCode::SessionEnded,
+            CloseCode::RateLimited,
+        ];
+
+        for code in codes {
+            assert!((4000..5000).contains(&code.code()), "{:?} outside 4000-4999", code);
+            assert!(!code.description().is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod delivery_ack_tests {
+    use super::*;
+
+    #[test]
+    fn test_ack_echoes_client_msg_id() {
+        let message_id = Uuid::new_v4();
+        let msg = ServerMessage::Ack {
+            client_msg_id: Some("c-9".to_string()),
+            message_id,
+            seq: Some(12),
+            sent_at: Utc::now(),
+        };
+
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert_eq!(json["type"], "ack");
+        assert_eq!(json["client_msg_id"], "c-9");
+        assert_eq!(json["message_id"], message_id.to_string());
+        assert_eq!(json["seq"], 12);
+    }
+
+    #[test]
+    fn test_ack_omits_missing_seq() {
+        let msg = ServerMessage::Ack {
+            client_msg_id: None,
+            message_id: Uuid::new_v4(),
+            seq: None,
+            sent_at: Utc::now(),
+        };
+
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert!(json.get("seq").is_none(), "Direct message acks are not sequenced");
+    }
+
+    #[test]
+    fn test_nack_carries_error_code() {
+        let msg = ServerMessage::nack(ErrorCode::PublishFailed, "retry", Some("c-10".to_string()));
+
+        let json: Value = serde_json::from_str(&msg.to_json()).unwrap();
+
+        assert_eq!(json["type"], "nack");
+        assert_eq!(json["client_msg_id"], "c-10");
+        assert_eq!(json["code"], "publish_failed");
+    }
+}
@@ -0,0 +1,64 @@
+use realtime_service::services::rate_limiter::{RateLimit, TokenBucket};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod token_bucket_tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit { burst: 3.0, per_second: 2.0 };
+
+    fn drain(bucket: &mut TokenBucket, now: Instant) {
+        while bucket.wait_time(now).is_none() {
+            bucket.take(now);
+        }
+    }
+
+    #[test]
+    fn test_allows_burst_then_waits() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(LIMIT, now);
+
+        for _ in 0..3 {
+            assert_eq!(bucket.wait_time(now), None);
+            bucket.take(now);
+        }
+
+        let wait = bucket.wait_time(now).expect("Burst should be used up");
+        assert!(wait <= Duration::from_millis(500) && wait > Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(LIMIT, start);
+        drain(&mut bucket, start);
+
+        assert_eq!(bucket.wait_time(start + Duration::from_millis(500)), None);
+    }
+
+    #[test]
+    fn test_refill_is_capped_at_burst() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(LIMIT, start);
+        let later = start + Duration::from_secs(60);
+
+        let mut taken = 0;
+        while bucket.wait_time(later).is_none() {
+            bucket.take(later);
+            taken += 1;
+        }
+
+        assert_eq!(taken, 3);
+    }
+
+    #[test]
+    fn test_zero_rate_disables_limit() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(RateLimit { burst: 0.0, per_second: 0.0 }, now);
+
+        for _ in 0..100 {
+            assert_eq!(bucket.wait_time(now), None);
+            bucket.take(now);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,131 @@
+use realtime_service::services::content_validation::ContentError;
+use realtime_service::services::sanitizer::{extract_links, strip_html};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod strip_html_tests {
+    use super::*;
+
+    #[test]
+    fn test_removes_tags_and_comments() {
+        assert_eq!(strip_html("<b>bold</b> and <i class=\"x\">italic</i>"), "bold and italic");
+        assert_eq!(strip_html("a<!-- hidden -->b<br/>c"), "abc");
+        assert_eq!(strip_html("<script>alert(1)</script>"), "alert(1)");
+    }
+
+    #[test]
+    fn test_keeps_angle_brackets_that_are_not_tags() {
+        assert_eq!(strip_html("a < b and b > c"), "a < b and b > c");
+        assert_eq!(strip_html("<3 thanks"), "<3 thanks");
+        assert_eq!(strip_html("<https://example.com>"), "<https://example.com>");
+        assert_eq!(strip_html("unclosed <b"), "unclosed <b");
+    }
+
+    #[test]
+    fn test_keeps_code_and_comparisons() {
+        assert_eq!(strip_html("if a<b and c>d then swap"), "if a<b and c>d then swap");
+        assert_eq!(strip_html("use Vec<String> here"), "use Vec<String> here");
+        assert_eq!(strip_html("Option<T> vs Result<T, E>"), "Option<T> vs Result<T, E>");
+    }
+
+    #[test]
+    fn test_removes_tags_with_attributes() {
+        assert_eq!(strip_html("<img src=x onerror=alert(1)>"), "");
+        assert_eq!(strip_html("<a href='https://example.com' data-id=\"1\">link</a>"), "link");
+        assert_eq!(strip_html("<IMG SRC=\"a>b\">"), "");
+    }
+
+    #[test]
+    fn test_nested_tags_cannot_reassemble() {
+        assert_eq!(strip_html("<scr<script>ipt>alert(1)</scr</script>ipt>"), "ipt>alert(1)ipt>");
+        assert_eq!(strip_html("<<b>img src=x onerror=alert(1)>"), "img src=x onerror=alert(1)>");
+        assert_eq!(strip_html("<im<!-- -->g src=x>"), "g src=x>");
+    }
+
+    #[test]
+    fn test_deeply_nested_input_is_linear() {
+        let nested = format!("{}{}", "<".repeat(12_000), "a>".repeat(6_000));
+        let unclosed_comments = "<!--".repeat(6_000);
+
+        let started = Instant::now();
+        assert!(!strip_html(&nested).contains("<a"));
+        strip_html(&unclosed_comments);
+        assert!(started.elapsed() < Duration::from_millis(100), "took {:?}", started.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod extract_links_tests {
+    use super::*;
+
+    #[test]
+    fn test_finds_http_and_www_links() {
+        let links = extract_links("see https://example.com/a?b=1 or www.example.org").unwrap();
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].url, "https://example.com/a?b=1");
+        assert_eq!((links[0].start, links[0].end), (4, 29));
+        assert_eq!(links[1].url, "https://www.example.org/");
+        assert_eq!((links[1].start, links[1].end), (33, 48));
+    }
+
+    #[test]
+    fn test_trims_surrounding_punctuation() {
+        let links = extract_links("(docs: https://example.com/page).").unwrap();
+        assert_eq!(links[0].url, "https://example.com/page");
+
+        let links = extract_links("https://en.wikipedia.org/wiki/Rust_(language)!").unwrap();
+        assert_eq!(links[0].url, "https://en.wikipedia.org/wiki/Rust_(language)");
+    }
+
+    #[test]
+    fn test_offsets_are_utf16_code_units() {
+        let links = extract_links("👍 https://example.com").unwrap();
+        assert_eq!((links[0].start, links[0].end), (3, 22));
+    }
+
+    #[test]
+    fn test_ignores_text_that_is_not_a_link() {
+        assert!(extract_links("note: http:// alone, www. and example.com").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rejects_dangerous_schemes() {
+        assert_eq!(
+            extract_links("click javascript:alert(1)"),
+            Err(ContentError::UnsafeLink { scheme: "javascript".to_string() })
+        );
+        assert_eq!(
+            extract_links("(DATA:text/html;base64,PHNjcmlwdD4=)"),
+            Err(ContentError::UnsafeLink { scheme: "data".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_rejects_dangerous_schemes_inside_tokens() {
+        assert_eq!(
+            extract_links("[click](javascript:alert(1))"),
+            Err(ContentError::UnsafeLink { scheme: "javascript".to_string() })
+        );
+        assert_eq!(
+            extract_links("href=\"vbscript:msgbox(1)\""),
+            Err(ContentError::UnsafeLink { scheme: "vbscript".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_allows_scheme_names_in_prose() {
+        assert!(extract_links("data: see the attached chart").is_ok());
+        assert!(extract_links("metadata:done").is_ok(), "Only whole scheme names count");
+        assert!(extract_links("error in file:main.rs line 4").is_ok());
+        assert!(extract_links("see data:1234").is_ok());
+    }
+
+    #[test]
+    fn test_rejects_file_links() {
+        assert_eq!(
+            extract_links("open file:///etc/passwd"),
+            Err(ContentError::UnsafeLink { scheme: "file".to_string() })
+        );
+    }
+}
\ No newline at end of file
@@ -0,0 +1,327 @@
+use realtime_service::services::session_registry::SessionRegistry;
+use uuid::Uuid;
+
+#[cfg(test)]
+mod coach_tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_session_has_no_coach() {
+        let registry = SessionRegistry::new();
+        assert!(!registry.is_coach(Uuid::new_v4(), Uuid::new_v4()));
+    }
+
+    #[test]
+    fn test_record_created_sets_coach() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let coach_id = Uuid::new_v4();
+
+        registry.record_created(session_id, coach_id);
+
+        assert!(registry.is_coach(session_id, coach_id));
+        assert!(!registry.is_coach(session_id, Uuid::new_v4()));
+        assert!(!registry.is_coach(Uuid::new_v4(), coach_id), "Coach role is per session");
+    }
+}
+
+#[cfg(test)]
+mod membership_tests {
+    use super::*;
+
+    #[test]
+    fn test_coach_and_joined_users_are_members() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let coach_id = Uuid::new_v4();
+        let mentee_id = Uuid::new_v4();
+
+        registry.record_created(session_id, coach_id);
+        registry.record_joined(session_id, mentee_id);
+
+        assert!(registry.is_member(session_id, coach_id));
+        assert!(registry.is_member(session_id, mentee_id));
+        assert!(!registry.is_member(session_id, Uuid::new_v4()));
+        assert!(!registry.is_coach(session_id, mentee_id), "Joining does not grant the coach role");
+    }
+
+    #[test]
+    fn test_sessions_become_known_once_created() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+
+        assert!(!registry.is_known(session_id));
+        registry.record_joined(session_id, Uuid::new_v4());
+        assert!(!registry.is_known(session_id), "A join alone does not mean every member is known");
+
+        registry.record_created(session_id, Uuid::new_v4());
+        assert!(registry.is_known(session_id));
+    }
+
+    #[test]
+    fn test_coach_from_lookup_does_not_complete_the_roster() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let coach_id = Uuid::new_v4();
+
+        registry.record_coach(session_id, coach_id);
+
+        assert!(registry.is_coach(session_id, coach_id));
+        assert!(registry.is_member(session_id, coach_id));
+        assert!(!registry.is_known(session_id), "Participants still need a lookup");
+    }
+
+    #[test]
+    fn test_cached_member_does_not_exclude_others() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let (mentee_id, coach_id) = (Uuid::new_v4(), Uuid::new_v4());
+
+        registry.record_joined(session_id, mentee_id);
+
+        assert!(registry.is_member(session_id, mentee_id));
+        assert!(!registry.is_member(session_id, coach_id));
+        assert!(!registry.is_known(session_id), "Other users still need a lookup");
+    }
+
+    #[test]
+    fn test_join_before_create_is_kept() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let mentee_id = Uuid::new_v4();
+
+        registry.record_joined(session_id, mentee_id);
+        registry.record_created(session_id, Uuid::new_v4());
+
+        assert!(registry.is_member(session_id, mentee_id));
+    }
+}
+
+#[cfg(test)]
+mod moderation_tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_mute_expires() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        let until = Utc::now() + Duration::minutes(5);
+
+        registry.mute(session_id, user_id, until);
+        assert_eq!(registry.muted_until(session_id, user_id), Some(until));
+
+        registry.mute(session_id, user_id, Utc::now() - Duration::seconds(1));
+        assert_eq!(registry.muted_until(session_id, user_id), None, "Elapsed mutes no longer apply");
+    }
+
+    #[test]
+    fn test_unmute_lifts_mute() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+
+        registry.mute(session_id, user_id, Utc::now() + Duration::minutes(5));
+        registry.unmute(session_id, user_id);
+
+        assert_eq!(registry.muted_until(session_id, user_id), None);
+    }
+
+    #[test]
+    fn test_ban_is_per_session() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+
+        registry.ban(session_id, user_id);
+
+        assert!(registry.is_banned(session_id, user_id));
+        assert!(!registry.is_banned(Uuid::new_v4(), user_id));
+
+        registry.unban(session_id, user_id);
+        assert!(!registry.is_banned(session_id, user_id));
+    }
+}
+
+#[cfg(test)]
+mod chat_settings_tests {
+    use super::*;
+    use realtime_service::model::protocol::ChatMode;
+    use std::time::Duration;
+
+    #[test]
+    fn test_open_mode_allows_everyone() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+
+        assert!(registry.chat_settings(session_id).is_default());
+        assert_eq!(registry.post_restriction(session_id, Uuid::new_v4(), false), None);
+    }
+
+    #[test]
+    fn test_announcement_only_allows_coach_and_co_hosts() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let (co_host, mentee) = (Uuid::new_v4(), Uuid::new_v4());
+
+        registry.set_chat_mode(session_id, ChatMode::AnnouncementOnly);
+        assert!(registry.set_co_host(session_id, co_host, true).is_some());
+
+        assert_eq!(registry.post_restriction(session_id, Uuid::new_v4(), true), None);
+        assert_eq!(registry.post_restriction(session_id, co_host, false), None);
+        assert_eq!(registry.post_restriction(session_id, mentee, false), Some(ChatMode::AnnouncementOnly));
+
+        assert!(registry.set_co_host(session_id, co_host, false).is_some());
+        assert!(registry.set_co_host(session_id, co_host, false).is_none(), "Unchanged settings are not re-announced");
+        assert_eq!(registry.post_restriction(session_id, co_host, false), Some(ChatMode::AnnouncementOnly));
+    }
+
+    #[test]
+    fn test_disabled_mode_blocks_coach_too() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+
+        registry.set_chat_mode(session_id, ChatMode::Disabled);
+
+        assert_eq!(registry.post_restriction(session_id, Uuid::new_v4(), true), Some(ChatMode::Disabled));
+    }
+
+    #[test]
+    fn test_slow_mode_exempts_coach_and_co_hosts() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let (co_host, mentee) = (Uuid::new_v4(), Uuid::new_v4());
+        let interval = Duration::from_secs(30);
+
+        let settings = registry.set_slow_mode(session_id, Some(interval));
+        registry.set_co_host(session_id, co_host, true);
+
+        assert_eq!(settings.slow_mode, Some(interval));
+        assert_eq!(registry.slow_mode_for(session_id, mentee, false), Some(interval));
+        assert_eq!(registry.slow_mode_for(session_id, co_host, false), None);
+        assert_eq!(registry.slow_mode_for(session_id, Uuid::new_v4(), true), None);
+
+        assert_eq!(registry.set_slow_mode(session_id, Some(Duration::ZERO)).slow_mode, None, "A zero interval lifts slow mode");
+    }
+
+    #[test]
+    fn test_settings_last_until_the_session_ends() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+
+        registry.set_chat_mode(session_id, ChatMode::Disabled);
+        assert_eq!(registry.chat_settings(session_id).mode, ChatMode::Disabled);
+
+        registry.record_ended(session_id);
+        assert!(registry.chat_settings(session_id).is_default());
+    }
+}
+
+#[cfg(test)]
+mod schedule_tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn registry() -> SessionRegistry {
+        SessionRegistry::with_early_admission(std::time::Duration::from_secs(15 * 60))
+    }
+
+    #[test]
+    fn test_unscheduled_sessions_are_open() {
+        let registry = registry();
+        let session_id = Uuid::new_v4();
+        registry.record_created(session_id, Uuid::new_v4());
+
+        assert_eq!(registry.start_pending(session_id, Utc::now()), None);
+        assert_eq!(registry.start_pending(Uuid::new_v4(), Utc::now()), None);
+    }
+
+    #[test]
+    fn test_connections_before_window_are_refused() {
+        let registry = registry();
+        let session_id = Uuid::new_v4();
+        let start_at = Utc::now() + Duration::hours(1);
+        registry.record_schedule(session_id, start_at);
+
+        assert_eq!(registry.start_pending(session_id, start_at - Duration::minutes(16)), Some(start_at));
+    }
+
+    #[test]
+    fn test_connections_within_window_are_admitted() {
+        let registry = registry();
+        let session_id = Uuid::new_v4();
+        let start_at = Utc::now() + Duration::hours(1);
+        registry.record_schedule(session_id, start_at);
+
+        assert_eq!(registry.start_pending(session_id, start_at - Duration::minutes(15)), None);
+        assert_eq!(registry.start_pending(session_id, start_at + Duration::hours(2)), None);
+    }
+}
+
+#[cfg(test)]
+mod lifecycle_tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_started_session_opens_before_window() {
+        let registry = SessionRegistry::with_early_admission(std::time::Duration::from_secs(15 * 60));
+        let session_id = Uuid::new_v4();
+        registry.record_schedule(session_id, Utc::now() + Duration::hours(2));
+
+        registry.record_started(session_id);
+
+        assert_eq!(registry.start_pending(session_id, Utc::now()), None);
+    }
+
+    #[test]
+    fn test_ended_session_forgets_members() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let coach_id = Uuid::new_v4();
+        registry.record_created(session_id, coach_id);
+
+        registry.record_ended(session_id);
+        registry.record_joined(session_id, Uuid::new_v4());
+
+        assert!(registry.is_ended(session_id), "Late events must not reopen the session");
+        assert!(!registry.is_coach(session_id, coach_id));
+        assert!(!registry.is_ended(Uuid::new_v4()));
+    }
+}
+
+#[cfg(test)]
+mod eviction_tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn test_ended_session_evicted_after_retention() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        registry.record_ended(session_id);
+
+        assert_eq!(registry.evict_stale(Utc::now() + Duration::hours(23)), 0);
+        assert!(registry.is_ended(session_id));
+
+        assert_eq!(registry.evict_stale(Utc::now() + Duration::hours(25)), 1);
+        assert!(!registry.is_ended(session_id));
+    }
+
+    #[test]
+    fn test_scheduled_session_kept_until_after_start() {
+        let registry = SessionRegistry::new();
+        let session_id = Uuid::new_v4();
+        let coach_id = Uuid::new_v4();
+        let start_at = Utc::now() + Duration::days(3);
+        registry.record_created(session_id, coach_id);
+        registry.record_schedule(session_id, start_at);
+
+        assert_eq!(registry.evict_stale(start_at + Duration::hours(23)), 0);
+        assert!(registry.is_coach(session_id, coach_id));
+
+        assert_eq!(registry.evict_stale(start_at + Duration::hours(25)), 1);
+        assert!(!registry.is_known(session_id));
+    }
+}
@@ -0,0 +1,1367 @@
+use realtime_service::auth::jwt::Claims;
+use realtime_service::model::chat_message::{BroadcastMessage, ChatMessage, SenderInfo};
+use realtime_service::services::content_validation::SanitizedContent;
+use realtime_service::services::session_manager::{
+    Connection, DeliveryReceipt, Replay, SessionManager, SessionManagerConfig, StampedMessage,
+};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+fn claims(user_id: Uuid, name: &str) -> Claims {
+    Claims {
+        sub: user_id,
+        name: name.to_string(),
+        email: format!("{}@example.com", name.to_lowercase()),
+        exp: usize::MAX,
+    }
+}
+
+fn create_connection(user_id: Uuid, name: &str) -> (Connection, mpsc::Receiver<String>) {
+    let (tx, rx) = mpsc::channel(16);
+    (Connection { sender: tx, user_info: claims(user_id, name), closer: None }, rx)
+}
+
+fn sender(name: &str) -> SenderInfo {
+    SenderInfo { id: Uuid::new_v4(), name: name.to_string() }
+}
+
+/// Drains every frame queued for a connection and keeps those of `frame_type`.
+fn frames_of_type(rx: &mut mpsc::Receiver<String>, frame_type: &str) -> Vec<Value> {
+    let mut frames = Vec::new();
+    while let Ok(frame) = rx.try_recv() {
+        let json: Value = serde_json::from_str(&frame).expect("Frames should be valid JSON");
+        if json["type"] == frame_type {
+            frames.push(json);
+        }
+    }
+    frames
+}
+
+fn replayed(replay: Replay) -> Vec<BroadcastMessage> {
+    match replay {
+        Replay::Messages(messages) => messages,
+        other => panic!("Expected replayed messages, got {:?}", other),
+    }
+}
+
+fn chat(content: &str) -> ChatMessage {
+    ChatMessage { content: content.to_string(), ..ChatMessage::default() }
+}
+
+fn stamp_fresh(manager: &SessionManager, session_id: Uuid, sender: SenderInfo, content: &str) -> BroadcastMessage {
+    match manager.stamp_chat_message(session_id, sender, chat(content), None) {
+        Some(StampedMessage::Fresh(message)) => message,
+        other => panic!("Expected a fresh message, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod sequence_tests {
+    use super::*;
+
+    #[test]
+    fn test_stamp_assigns_increasing_sequence_numbers() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let first = stamp_fresh(&manager, session_id, sender("Alice"), "one");
+        let second = stamp_fresh(&manager, session_id, sender("Alice"), "two");
+
+        assert_eq!(first.seq, 1);
+        assert_eq!(second.seq, 2);
+        assert_ne!(first.id, second.id, "Every message should get a unique id");
+        assert!(second.sent_at >= first.sent_at);
+    }
+
+    #[test]
+    fn test_sequence_numbers_are_per_session() {
+        let manager = SessionManager::new();
+        let session_a = Uuid::new_v4();
+        let session_b = Uuid::new_v4();
+        let (conn_a, _rx_a) = create_connection(Uuid::new_v4(), "Alice");
+        let (conn_b, _rx_b) = create_connection(Uuid::new_v4(), "Bob");
+        manager.insert(session_a, 1, conn_a, None);
+        manager.insert(session_b, 2, conn_b, None);
+
+        stamp_fresh(&manager, session_a, sender("Alice"), "a1");
+        let a2 = stamp_fresh(&manager, session_a, sender("Alice"), "a2");
+        let b1 = stamp_fresh(&manager, session_b, sender("Bob"), "b1");
+
+        assert_eq!(a2.seq, 2);
+        assert_eq!(b1.seq, 1);
+    }
+
+    #[test]
+    fn test_sequence_numbers_continue_after_room_empties() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+        stamp_fresh(&manager, session_id, sender("Alice"), "one");
+        stamp_fresh(&manager, session_id, sender("Alice"), "two");
+
+        manager.remove(session_id, 1);
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Bob");
+        assert!(replayed(manager.insert(session_id, 2, conn, Some(2))).is_empty(), "Resume point is still current");
+
+        assert_eq!(stamp_fresh(&manager, session_id, sender("Bob"), "three").seq, 3);
+    }
+
+    #[test]
+    fn test_ended_session_restarts_numbering() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+        stamp_fresh(&manager, session_id, sender("Alice"), "one");
+        manager.remove(session_id, 1);
+
+        manager.end_session(session_id, "{}");
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 2, conn, None);
+
+        assert_eq!(stamp_fresh(&manager, session_id, sender("Alice"), "again").seq, 1);
+    }
+
+    #[test]
+    fn test_stamp_unknown_session_returns_none() {
+        let manager = SessionManager::new();
+        let result = manager.stamp_chat_message(Uuid::new_v4(), sender("Ghost"), chat("boo"), None);
+
+        assert!(result.is_none());
+    }
+}
+
+#[cfg(test)]
+mod idempotency_tests {
+    use super::*;
+
+    #[test]
+    fn test_resubmitted_key_returns_original_receipt() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice = sender("Alice");
+        let (conn, _rx) = create_connection(alice.id, "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let first = match manager.stamp_chat_message(session_id, alice.clone(), chat("hi"), Some("c-1")) {
+            Some(StampedMessage::Fresh(message)) => message,
+            other => panic!("Expected a fresh message, got {:?}", other),
+        };
+
+        match manager.stamp_chat_message(session_id, alice.clone(), chat("hi"), Some("c-1")) {
+            Some(StampedMessage::Duplicate(receipt)) => {
+                assert_eq!(receipt.message_id, first.id);
+                assert_eq!(receipt.seq, Some(first.seq));
+            }
+            other => panic!("Expected a duplicate, got {:?}", other),
+        }
+
+        let next = stamp_fresh(&manager, session_id, alice, "next");
+        assert_eq!(next.seq, first.seq + 1, "Duplicates must not consume sequence numbers");
+    }
+
+    #[test]
+    fn test_same_key_from_different_users_is_not_a_duplicate() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let first = manager.stamp_chat_message(session_id, sender("Alice"), chat("a"), Some("c-1"));
+        let second = manager.stamp_chat_message(session_id, sender("Bob"), chat("b"), Some("c-1"));
+
+        assert!(matches!(first, Some(StampedMessage::Fresh(_))));
+        assert!(matches!(second, Some(StampedMessage::Fresh(_))));
+    }
+
+    #[test]
+    fn test_forgotten_key_can_be_resubmitted() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice = sender("Alice");
+        let (conn, _rx) = create_connection(alice.id, "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        manager.stamp_chat_message(session_id, alice.clone(), chat("hi"), Some("c-1"));
+        manager.forget_idempotency_key(session_id, alice.id, "c-1");
+
+        let retry = manager.stamp_chat_message(session_id, alice, chat("hi"), Some("c-1"));
+        assert!(matches!(retry, Some(StampedMessage::Fresh(_))));
+    }
+
+    #[test]
+    fn test_claimed_key_returns_first_receipt() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice = sender("Alice");
+        let (conn, _rx) = create_connection(alice.id, "Alice");
+        manager.insert(session_id, 1, conn, None);
+        let receipt = |message_id| DeliveryReceipt { message_id, seq: None, sent_at: chrono::Utc::now() };
+        let first_id = Uuid::new_v4();
+
+        assert!(manager.claim_idempotency_key(session_id, alice.id, "d-1", receipt(first_id)).is_none());
+
+        let duplicate = manager.claim_idempotency_key(session_id, alice.id, "d-1", receipt(Uuid::new_v4()));
+        assert_eq!(duplicate.map(|receipt| receipt.message_id), Some(first_id));
+        assert_eq!(manager.delivery_receipt(session_id, alice.id, "d-1").map(|receipt| receipt.seq), Some(None));
+    }
+
+    #[test]
+    fn test_key_survives_an_empty_room() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice = sender("Alice");
+        let (conn, _rx) = create_connection(alice.id, "Alice");
+        manager.insert(session_id, 1, conn, None);
+        manager.stamp_chat_message(session_id, alice.clone(), chat("hi"), Some("c-1"));
+
+        manager.remove(session_id, 1);
+        let (conn, _rx) = create_connection(alice.id, "Alice");
+        manager.insert(session_id, 2, conn, None);
+
+        let retry = manager.stamp_chat_message(session_id, alice, chat("hi"), Some("c-1"));
+        assert!(matches!(retry, Some(StampedMessage::Duplicate(_))), "A retry after reconnecting is still deduplicated");
+    }
+
+    #[test]
+    fn test_key_expires_after_window() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            idempotency_window: Duration::from_millis(20),
+            ..SessionManagerConfig::default()
+        });
+        let session_id = Uuid::new_v4();
+        let alice = sender("Alice");
+        let (conn, _rx) = create_connection(alice.id, "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        manager.stamp_chat_message(session_id, alice.clone(), chat("hi"), Some("c-1"));
+        std::thread::sleep(Duration::from_millis(40));
+
+        let retry = manager.stamp_chat_message(session_id, alice, chat("hi"), Some("c-1"));
+        assert!(matches!(retry, Some(StampedMessage::Fresh(_))));
+    }
+
+    #[test]
+    fn test_delivery_receipt_looks_up_key_without_stamping() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice = sender("Alice");
+        let (conn, _rx) = create_connection(alice.id, "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        assert_eq!(manager.delivery_receipt(session_id, alice.id, "c-1"), None);
+        let first = match manager.stamp_chat_message(session_id, alice.clone(), chat("hi"), Some("c-1")) {
+            Some(StampedMessage::Fresh(message)) => message,
+            other => panic!("Expected a fresh message, got {:?}", other),
+        };
+
+        let receipt = manager.delivery_receipt(session_id, alice.id, "c-1").expect("Key should be remembered");
+        assert_eq!(receipt.message_id, first.id);
+        assert_eq!(manager.delivery_receipt(session_id, Uuid::new_v4(), "c-1"), None, "Keys are per user");
+    }
+}
+
+#[cfg(test)]
+mod history_replay_tests {
+    use super::*;
+
+    fn manager_with_history(limit: usize, max_age: Duration) -> SessionManager {
+        SessionManager::with_config(SessionManagerConfig {
+            history_limit: limit,
+            history_max_age: max_age,
+            ..SessionManagerConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_new_connection_receives_recent_history() {
+        let manager = manager_with_history(10, Duration::from_secs(60));
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        assert!(replayed(manager.insert(session_id, 1, conn, None)).is_empty(), "First connection has nothing to replay");
+
+        for content in ["one", "two", "three"] {
+            let message = stamp_fresh(&manager, session_id, sender("Alice"), content);
+            manager.broadcast_chat_message(session_id, message, Some(1));
+        }
+
+        let (late_conn, _late_rx) = create_connection(Uuid::new_v4(), "Bob");
+        let history = replayed(manager.insert(session_id, 2, late_conn, None));
+
+        let contents: Vec<&str> = history.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_history_is_bounded_by_limit() {
+        let manager = manager_with_history(2, Duration::from_secs(60));
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        for content in ["one", "two", "three"] {
+            let message = stamp_fresh(&manager, session_id, sender("Alice"), content);
+            manager.broadcast_chat_message(session_id, message, None);
+        }
+
+        let (late_conn, _late_rx) = create_connection(Uuid::new_v4(), "Bob");
+        let history = replayed(manager.insert(session_id, 2, late_conn, None));
+
+        let seqs: Vec<u64> = history.iter().map(|m| m.seq).collect();
+        assert_eq!(seqs, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_history_is_kept_in_sequence_order() {
+        let manager = manager_with_history(10, Duration::from_secs(60));
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let first = stamp_fresh(&manager, session_id, sender("Alice"), "first");
+        let second = stamp_fresh(&manager, session_id, sender("Bob"), "second");
+        manager.broadcast_chat_message(session_id, second, None);
+        manager.broadcast_chat_message(session_id, first, None);
+
+        let (late_conn, _late_rx) = create_connection(Uuid::new_v4(), "Carol");
+        let seqs: Vec<u64> = replayed(manager.insert(session_id, 2, late_conn, None)).iter().map(|m| m.seq).collect();
+        assert_eq!(seqs, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_old_messages_are_not_replayed() {
+        let manager = manager_with_history(10, Duration::from_millis(20));
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let message = stamp_fresh(&manager, session_id, sender("Alice"), "stale");
+        manager.broadcast_chat_message(session_id, message, None);
+        std::thread::sleep(Duration::from_millis(40));
+
+        let (late_conn, _late_rx) = create_connection(Uuid::new_v4(), "Bob");
+        assert!(replayed(manager.insert(session_id, 2, late_conn, None)).is_empty());
+    }
+
+    #[test]
+    fn test_history_survives_a_briefly_empty_room() {
+        let manager = manager_with_history(10, Duration::from_secs(60));
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let message = stamp_fresh(&manager, session_id, sender("Alice"), "bye");
+        manager.broadcast_chat_message(session_id, message, None);
+        manager.remove(session_id, 1);
+
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Bob");
+        assert_eq!(replayed(manager.insert(session_id, 2, conn, Some(0))).len(), 1, "Resume still works");
+    }
+
+    #[test]
+    fn test_history_dropped_once_idle_window_passes() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            idempotency_window: Duration::from_millis(20),
+            history_limit: 10,
+            history_max_age: Duration::from_secs(60),
+            ..SessionManagerConfig::default()
+        });
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let message = stamp_fresh(&manager, session_id, sender("Alice"), "bye");
+        manager.broadcast_chat_message(session_id, message, None);
+        manager.remove(session_id, 1);
+        std::thread::sleep(Duration::from_millis(40));
+
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        assert!(replayed(manager.insert(session_id, 1, conn, None)).is_empty());
+        assert_eq!(stamp_fresh(&manager, session_id, sender("Alice"), "again").seq, 2, "Numbering continues");
+    }
+
+    #[test]
+    fn test_idle_session_dropped_after_retention() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            idle_retention: Duration::from_millis(20),
+            history_limit: 10,
+            ..SessionManagerConfig::default()
+        });
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let message = stamp_fresh(&manager, session_id, sender("Alice"), "bye");
+        manager.broadcast_chat_message(session_id, message, None);
+        manager.remove(session_id, 1);
+
+        assert_eq!(manager.expire_idle_sessions(), 0, "Kept within the retention");
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(manager.expire_idle_sessions(), 1);
+
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        assert!(replayed(manager.insert(session_id, 1, conn, None)).is_empty());
+        assert_eq!(stamp_fresh(&manager, session_id, sender("Alice"), "again").seq, 1, "Numbering starts over");
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_chat_message_skips_sender() {
+        let manager = manager_with_history(10, Duration::from_secs(60));
+        let session_id = Uuid::new_v4();
+        let (sender_conn, mut sender_rx) = create_connection(Uuid::new_v4(), "Alice");
+        let (other_conn, mut other_rx) = create_connection(Uuid::new_v4(), "Bob");
+        manager.insert(session_id, 1, sender_conn, None);
+        manager.insert(session_id, 2, other_conn, None);
+
+        let message = stamp_fresh(&manager, session_id, sender("Alice"), "hello");
+        manager.broadcast_chat_message(session_id, message, Some(1));
+
+        let received = frames_of_type(&mut other_rx, "chat_message");
+        assert_eq!(received.len(), 1, "Other participant should receive the message");
+        assert!(frames_of_type(&mut sender_rx, "chat_message").is_empty(), "Sender should be skipped");
+    }
+}
+
+#[cfg(test)]
+mod resume_tests {
+    use super::*;
+
+    fn session_with_messages(manager: &SessionManager, count: usize) -> Uuid {
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        for i in 0..count {
+            let message = stamp_fresh(manager, session_id, sender("Alice"), &format!("m{}", i));
+            manager.broadcast_chat_message(session_id, message, None);
+        }
+
+        session_id
+    }
+
+    #[test]
+    fn test_resume_replays_only_the_gap() {
+        let manager = SessionManager::new();
+        let session_id = session_with_messages(&manager, 5);
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Bob");
+
+        let seqs: Vec<u64> = replayed(manager.insert(session_id, 2, conn, Some(3)))
+            .iter()
+            .map(|m| m.seq)
+            .collect();
+
+        assert_eq!(seqs, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_resume_when_up_to_date_replays_nothing() {
+        let manager = SessionManager::new();
+        let session_id = session_with_messages(&manager, 3);
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Bob");
+
+        assert!(replayed(manager.insert(session_id, 2, conn, Some(3))).is_empty());
+    }
+
+    #[test]
+    fn test_resume_past_buffer_requires_resync() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            history_limit: 2,
+            ..SessionManagerConfig::default()
+        });
+        let session_id = session_with_messages(&manager, 5);
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Bob");
+
+        match manager.insert(session_id, 2, conn, Some(1)) {
+            Replay::ResyncRequired { latest_seq } => assert_eq!(latest_seq, 5),
+            other => panic!("Expected resync, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resume_from_previous_session_incarnation_requires_resync() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Bob");
+
+        match manager.insert(session_id, 1, conn, Some(42)) {
+            Replay::ResyncRequired { latest_seq } => assert_eq!(latest_seq, 0),
+            other => panic!("Expected resync, got {:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod presence_tests {
+    use super::*;
+
+    #[test]
+    fn test_new_connection_receives_presence_snapshot() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        let (alice_phone, _rx1) = create_connection(alice_id, "Alice");
+        let (alice_laptop, _rx2) = create_connection(alice_id, "Alice");
+        let (bob, mut bob_rx) = create_connection(Uuid::new_v4(), "Bob");
+
+        manager.insert(session_id, 1, alice_phone, None);
+        manager.insert(session_id, 2, alice_laptop, None);
+        manager.insert(session_id, 3, bob, None);
+
+        let presence = frames_of_type(&mut bob_rx, "presence");
+        assert_eq!(presence.len(), 1);
+
+        let names: Vec<&str> = presence[0]["participants"].as_array().unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["Alice", "Bob"], "Participants are distinct users, not connections");
+    }
+
+    #[test]
+    fn test_participant_joined_only_on_first_connection() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        let (bob, mut bob_rx) = create_connection(Uuid::new_v4(), "Bob");
+        let (alice_phone, _rx1) = create_connection(alice_id, "Alice");
+        let (alice_laptop, _rx2) = create_connection(alice_id, "Alice");
+
+        manager.insert(session_id, 1, bob, None);
+        manager.insert(session_id, 2, alice_phone, None);
+        manager.insert(session_id, 3, alice_laptop, None);
+
+        let joined = frames_of_type(&mut bob_rx, "participant_joined");
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0]["user_id"], alice_id.to_string());
+    }
+
+    #[test]
+    fn test_participant_left_only_on_last_connection() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        let (bob, mut bob_rx) = create_connection(Uuid::new_v4(), "Bob");
+        let (alice_phone, _rx1) = create_connection(alice_id, "Alice");
+        let (alice_laptop, _rx2) = create_connection(alice_id, "Alice");
+
+        manager.insert(session_id, 1, bob, None);
+        manager.insert(session_id, 2, alice_phone, None);
+        manager.insert(session_id, 3, alice_laptop, None);
+
+        manager.remove(session_id, 2);
+        assert!(frames_of_type(&mut bob_rx, "participant_left").is_empty(), "Alice still has a device connected");
+
+        manager.remove(session_id, 3);
+        let left = frames_of_type(&mut bob_rx, "participant_left");
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0]["user_id"], alice_id.to_string());
+    }
+}
+
+#[cfg(test)]
+mod typing_tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_typing_start_is_coalesced() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        let (alice, mut alice_rx) = create_connection(alice_id, "Alice");
+        let (bob, mut bob_rx) = create_connection(Uuid::new_v4(), "Bob");
+        manager.insert(session_id, 1, alice, None);
+        manager.insert(session_id, 2, bob, None);
+
+        for _ in 0..10 {
+            manager.set_typing(session_id, &claims(alice_id, "Alice"), true);
+        }
+
+        let typing = frames_of_type(&mut bob_rx, "typing");
+        assert_eq!(typing.len(), 1, "Only the first typing_start should be broadcast");
+        assert_eq!(typing[0]["is_typing"], true);
+        assert_eq!(typing[0]["user_id"], alice_id.to_string());
+        assert!(frames_of_type(&mut alice_rx, "typing").is_empty(), "Typist should not see their own indicator");
+    }
+
+    #[test]
+    fn test_typing_stop_is_broadcast_once_after_delay() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            typing_stop_delay: Duration::from_millis(20),
+            ..SessionManagerConfig::default()
+        });
+        let session_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        let (alice, _alice_rx) = create_connection(alice_id, "Alice");
+        let (bob, mut bob_rx) = create_connection(Uuid::new_v4(), "Bob");
+        manager.insert(session_id, 1, alice, None);
+        manager.insert(session_id, 2, bob, None);
+
+        manager.set_typing(session_id, &claims(alice_id, "Alice"), true);
+        manager.set_typing(session_id, &claims(alice_id, "Alice"), false);
+        manager.set_typing(session_id, &claims(alice_id, "Alice"), false);
+        manager.expire_typing();
+        assert_eq!(frames_of_type(&mut bob_rx, "typing").len(), 1, "The stop waits for the delay");
+
+        std::thread::sleep(Duration::from_millis(40));
+        manager.expire_typing();
+
+        let typing = frames_of_type(&mut bob_rx, "typing");
+        assert_eq!(typing.len(), 1);
+        assert_eq!(typing[0]["is_typing"], false);
+    }
+
+    #[test]
+    fn test_toggling_typing_is_throttled() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        let (alice, _alice_rx) = create_connection(alice_id, "Alice");
+        let (bob, mut bob_rx) = create_connection(Uuid::new_v4(), "Bob");
+        manager.insert(session_id, 1, alice, None);
+        manager.insert(session_id, 2, bob, None);
+
+        for _ in 0..10 {
+            manager.set_typing(session_id, &claims(alice_id, "Alice"), true);
+            manager.set_typing(session_id, &claims(alice_id, "Alice"), false);
+        }
+        manager.expire_typing();
+
+        assert_eq!(frames_of_type(&mut bob_rx, "typing").len(), 1, "Only the first start goes out before the delay");
+    }
+
+    #[test]
+    fn test_typing_indicator_expires() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            typing_timeout: Duration::from_millis(20),
+            ..SessionManagerConfig::default()
+        });
+        let session_id = Uuid::new_v4();
+        let alice_id = Uuid::new_v4();
+        let (alice, _alice_rx) = create_connection(alice_id, "Alice");
+        let (bob, mut bob_rx) = create_connection(Uuid::new_v4(), "Bob");
+        manager.insert(session_id, 1, alice, None);
+        manager.insert(session_id, 2, bob, None);
+
+        manager.set_typing(session_id, &claims(alice_id, "Alice"), true);
+        manager.expire_typing();
+        assert_eq!(frames_of_type(&mut bob_rx, "typing").len(), 1, "Indicator should still be active");
+
+        std::thread::sleep(Duration::from_millis(40));
+        manager.expire_typing();
+
+        let typing = frames_of_type(&mut bob_rx, "typing");
+        assert_eq!(typing.len(), 1);
+        assert_eq!(typing[0]["is_typing"], false);
+    }
+}
+
+#[cfg(test)]
+mod read_receipt_tests {
+    use super::*;
+
+    fn session_with_messages(manager: &SessionManager, reader_id: Uuid, count: usize) -> (Uuid, mpsc::Receiver<String>) {
+        let session_id = Uuid::new_v4();
+        let (reader, reader_rx) = create_connection(reader_id, "Mentee");
+        manager.insert(session_id, 1, reader, None);
+
+        for i in 0..count {
+            let message = stamp_fresh(manager, session_id, sender("Coach"), &format!("m{}", i));
+            manager.broadcast_chat_message(session_id, message, None);
+        }
+
+        (session_id, reader_rx)
+    }
+
+    #[test]
+    fn test_mark_read_advances_and_broadcasts() {
+        let manager = SessionManager::new();
+        let reader_id = Uuid::new_v4();
+        let (session_id, mut reader_rx) = session_with_messages(&manager, reader_id, 3);
+
+        assert_eq!(manager.mark_read(session_id, &claims(reader_id, "Mentee"), 2), Some(2));
+
+        let receipts = frames_of_type(&mut reader_rx, "read_receipt");
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0]["seq"], 2);
+        assert_eq!(receipts[0]["user_id"], reader_id.to_string());
+    }
+
+    #[test]
+    fn test_mark_read_never_moves_backwards() {
+        let manager = SessionManager::new();
+        let reader_id = Uuid::new_v4();
+        let (session_id, _reader_rx) = session_with_messages(&manager, reader_id, 3);
+        let reader = claims(reader_id, "Mentee");
+
+        manager.mark_read(session_id, &reader, 3);
+
+        assert_eq!(manager.mark_read(session_id, &reader, 1), None);
+        assert_eq!(manager.mark_read(session_id, &reader, 3), None);
+    }
+
+    #[test]
+    fn test_mark_read_is_clamped_to_latest_message() {
+        let manager = SessionManager::new();
+        let reader_id = Uuid::new_v4();
+        let (session_id, _reader_rx) = session_with_messages(&manager, reader_id, 2);
+
+        assert_eq!(manager.mark_read(session_id, &claims(reader_id, "Mentee"), 99), Some(2));
+    }
+}
+
+#[cfg(test)]
+mod message_change_tests {
+    use super::*;
+    use chrono::Utc;
+    use realtime_service::services::session_manager::MessageChangeError;
+
+    fn session_with_message(manager: &SessionManager, author: SenderInfo) -> (Uuid, Uuid, mpsc::Receiver<String>) {
+        let session_id = Uuid::new_v4();
+        let (conn, rx) = create_connection(author.id, &author.name);
+        manager.insert(session_id, 1, conn, None);
+
+        let message = stamp_fresh(manager, session_id, author, "original");
+        let message_id = message.id;
+        manager.broadcast_chat_message(session_id, message, None);
+
+        (session_id, message_id, rx)
+    }
+
+    #[test]
+    fn test_only_sender_or_coach_may_change_message() {
+        let manager = SessionManager::new();
+        let author = sender("Alice");
+        let author_id = author.id;
+        let (session_id, message_id, _rx) = session_with_message(&manager, author);
+
+        assert_eq!(manager.authorize_message_change(session_id, message_id, author_id, false), Ok(()));
+        assert_eq!(manager.authorize_message_change(session_id, message_id, Uuid::new_v4(), true), Ok(()));
+        assert_eq!(
+            manager.authorize_message_change(session_id, message_id, Uuid::new_v4(), false),
+            Err(MessageChangeError::Forbidden)
+        );
+        assert_eq!(
+            manager.authorize_message_change(session_id, Uuid::new_v4(), author_id, false),
+            Err(MessageChangeError::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_edit_updates_history_and_broadcasts() {
+        let manager = SessionManager::new();
+        let author = sender("Alice");
+        let author_id = author.id;
+        let (session_id, message_id, mut rx) = session_with_message(&manager, author);
+
+        let edit = SanitizedContent { content: "fixed typo".to_string(), links: Vec::new() };
+        manager.apply_edit(session_id, message_id, edit, author_id, Utc::now());
+
+        let edits = frames_of_type(&mut rx, "message_edited");
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0]["content"], "fixed typo");
+
+        let (late, _late_rx) = create_connection(Uuid::new_v4(), "Bob");
+        let history = replayed(manager.insert(session_id, 2, late, None));
+        assert_eq!(history[0].content, "fixed typo");
+        assert!(history[0].edited_at.is_some());
+    }
+
+    #[test]
+    fn test_delete_leaves_tombstone() {
+        let manager = SessionManager::new();
+        let author = sender("Alice");
+        let author_id = author.id;
+        let (session_id, message_id, mut rx) = session_with_message(&manager, author);
+
+        manager.apply_delete(session_id, message_id, author_id, Utc::now());
+
+        assert_eq!(frames_of_type(&mut rx, "message_deleted").len(), 1);
+        assert_eq!(
+            manager.authorize_message_change(session_id, message_id, author_id, false),
+            Err(MessageChangeError::NotFound),
+            "Deleted messages cannot be changed again"
+        );
+
+        let (late, _late_rx) = create_connection(Uuid::new_v4(), "Bob");
+        let history = replayed(manager.insert(session_id, 2, late, None));
+        assert!(history[0].content.is_empty());
+        assert!(history[0].deleted_at.is_some());
+    }
+}
+
+#[cfg(test)]
+mod reaction_tests {
+    use super::*;
+    use realtime_service::services::session_manager::ReactionError;
+
+    fn session_with_message(manager: &SessionManager) -> (Uuid, Uuid, mpsc::Receiver<String>) {
+        let session_id = Uuid::new_v4();
+        let (conn, rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let message = stamp_fresh(manager, session_id, sender("Alice"), "great question");
+        let message_id = message.id;
+        manager.broadcast_chat_message(session_id, message, None);
+
+        (session_id, message_id, rx)
+    }
+
+    #[test]
+    fn test_reactions_are_aggregated_per_emoji() {
+        let manager = SessionManager::new();
+        let (session_id, message_id, mut rx) = session_with_message(&manager);
+
+        manager.react(session_id, message_id, Uuid::new_v4(), "👍", true).unwrap();
+        manager.react(session_id, message_id, Uuid::new_v4(), "👍", true).unwrap();
+
+        let updates = frames_of_type(&mut rx, "reaction_updated");
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1]["count"], 2);
+        assert_eq!(updates[1]["added"], true);
+    }
+
+    #[test]
+    fn test_duplicate_reaction_is_ignored() {
+        let manager = SessionManager::new();
+        let (session_id, message_id, mut rx) = session_with_message(&manager);
+        let user_id = Uuid::new_v4();
+
+        manager.react(session_id, message_id, user_id, "🎉", true).unwrap();
+        manager.react(session_id, message_id, user_id, "🎉", true).unwrap();
+
+        assert_eq!(frames_of_type(&mut rx, "reaction_updated").len(), 1);
+    }
+
+    #[test]
+    fn test_unreact_decrements_and_updates_history() {
+        let manager = SessionManager::new();
+        let (session_id, message_id, mut rx) = session_with_message(&manager);
+        let user_id = Uuid::new_v4();
+
+        manager.react(session_id, message_id, user_id, "👍", true).unwrap();
+        manager.react(session_id, message_id, user_id, "👍", false).unwrap();
+
+        let updates = frames_of_type(&mut rx, "reaction_updated");
+        assert_eq!(updates[1]["count"], 0);
+        assert_eq!(updates[1]["added"], false);
+
+        let (late, _late_rx) = create_connection(Uuid::new_v4(), "Bob");
+        let history = replayed(manager.insert(session_id, 2, late, None));
+        assert!(history[0].reactions.is_empty());
+    }
+
+    #[test]
+    fn test_replayed_history_carries_reaction_counts() {
+        let manager = SessionManager::new();
+        let (session_id, message_id, _rx) = session_with_message(&manager);
+
+        manager.react(session_id, message_id, Uuid::new_v4(), "👍", true).unwrap();
+        manager.react(session_id, message_id, Uuid::new_v4(), "❤️", true).unwrap();
+        manager.react(session_id, message_id, Uuid::new_v4(), "👍", true).unwrap();
+
+        let (late, _late_rx) = create_connection(Uuid::new_v4(), "Bob");
+        let history = replayed(manager.insert(session_id, 2, late, None));
+        let thumbs = history[0].reactions.iter().find(|r| r.emoji == "👍").unwrap();
+        assert_eq!(thumbs.count, 2);
+        assert_eq!(history[0].reactions.len(), 2);
+    }
+
+    #[test]
+    fn test_distinct_reactions_are_limited() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            max_reactions_per_message: 2,
+            ..SessionManagerConfig::default()
+        });
+        let (session_id, message_id, _rx) = session_with_message(&manager);
+
+        manager.react(session_id, message_id, Uuid::new_v4(), "👍", true).unwrap();
+        manager.react(session_id, message_id, Uuid::new_v4(), "🎉", true).unwrap();
+
+        assert_eq!(
+            manager.react(session_id, message_id, Uuid::new_v4(), "🔥", true),
+            Err(ReactionError::LimitReached)
+        );
+        assert_eq!(
+            manager.react(session_id, message_id, Uuid::new_v4(), "👍", true),
+            Ok(()),
+            "Existing emoji can still be added"
+        );
+    }
+
+    #[test]
+    fn test_emoji_sequences_are_accepted() {
+        let manager = SessionManager::new();
+        let (session_id, message_id, _rx) = session_with_message(&manager);
+
+        for emoji in ["👍", "👍🏽", "❤️", "👩‍💻", "👨‍👩‍👧‍👦", "🇫🇷", "🏴󠁧󠁢󠁳󠁣󠁴󠁿", "#️⃣"] {
+            assert_eq!(manager.react(session_id, message_id, Uuid::new_v4(), emoji, true), Ok(()), "{} is one emoji", emoji);
+        }
+    }
+
+    #[test]
+    fn test_invalid_reactions_are_rejected() {
+        let manager = SessionManager::new();
+        let (session_id, message_id, _rx) = session_with_message(&manager);
+
+        assert_eq!(manager.react(session_id, message_id, Uuid::new_v4(), "", true), Err(ReactionError::InvalidEmoji));
+        assert_eq!(manager.react(session_id, message_id, Uuid::new_v4(), "a b", true), Err(ReactionError::InvalidEmoji));
+        assert_eq!(manager.react(session_id, message_id, Uuid::new_v4(), "lol", true), Err(ReactionError::InvalidEmoji));
+        assert_eq!(manager.react(session_id, message_id, Uuid::new_v4(), "👍👍", true), Err(ReactionError::InvalidEmoji));
+        assert_eq!(manager.react(session_id, message_id, Uuid::new_v4(), "1", true), Err(ReactionError::InvalidEmoji));
+        assert_eq!(
+            manager.react(session_id, Uuid::new_v4(), Uuid::new_v4(), "👍", true),
+            Err(ReactionError::MessageNotFound)
+        );
+    }
+}
+
+#[cfg(test)]
+mod thread_tests {
+    use super::*;
+
+    fn reply(manager: &SessionManager, session_id: Uuid, parent_id: Uuid, content: &str) -> BroadcastMessage {
+        let chat_msg = ChatMessage { reply_to: Some(parent_id), ..chat(content) };
+        match manager.stamp_chat_message(session_id, sender("Bob"), chat_msg, None) {
+            Some(StampedMessage::Fresh(message)) => message,
+            other => panic!("Expected a fresh message, got {:?}", other),
+        }
+    }
+
+    fn session_with_question(manager: &SessionManager, content: &str) -> (Uuid, BroadcastMessage) {
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+
+        let question = stamp_fresh(manager, session_id, sender("Alice"), content);
+        manager.broadcast_chat_message(session_id, question.clone(), None);
+        (session_id, question)
+    }
+
+    #[test]
+    fn test_reply_quotes_buffered_parent() {
+        let manager = SessionManager::new();
+        let (session_id, question) = session_with_question(&manager, "How do I start?");
+
+        let answer = reply(&manager, session_id, question.id, "Start small");
+
+        assert_eq!(answer.reply_to, Some(question.id));
+        let quoted = answer.quoted.expect("Buffered parents should be quoted");
+        assert_eq!(quoted.sender.name, "Alice");
+        assert_eq!(quoted.excerpt, "How do I start?");
+    }
+
+    #[test]
+    fn test_long_parent_is_truncated() {
+        let manager = SessionManager::new();
+        let (session_id, question) = session_with_question(&manager, &"é".repeat(500));
+
+        let quoted = reply(&manager, session_id, question.id, "ok").quoted.unwrap();
+
+        assert!(quoted.excerpt.chars().count() < 500);
+        assert!(quoted.excerpt.ends_with('…'));
+    }
+
+    #[test]
+    fn test_reply_to_unbuffered_parent_keeps_reference_only() {
+        let manager = SessionManager::new();
+        let (session_id, _) = session_with_question(&manager, "question");
+        let old_parent = Uuid::new_v4();
+
+        let answer = reply(&manager, session_id, old_parent, "answer");
+
+        assert_eq!(answer.reply_to, Some(old_parent));
+        assert!(answer.quoted.is_none());
+    }
+
+    #[test]
+    fn test_deleting_parent_drops_replayed_quote() {
+        let manager = SessionManager::new();
+        let (session_id, question) = session_with_question(&manager, "secret");
+        let answer = reply(&manager, session_id, question.id, "answer");
+        manager.broadcast_chat_message(session_id, answer, None);
+
+        manager.apply_delete(session_id, question.id, Uuid::new_v4(), chrono::Utc::now());
+
+        let (late, _late_rx) = create_connection(Uuid::new_v4(), "Carol");
+        let history = replayed(manager.insert(session_id, 2, late, None));
+        let replayed_answer = history.iter().find(|m| m.reply_to == Some(question.id)).unwrap();
+        assert!(replayed_answer.quoted.is_none());
+    }
+}
+
+#[cfg(test)]
+mod mention_tests {
+    use super::*;
+
+    #[test]
+    fn test_mentions_are_deduplicated_in_order() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+        let (bob, carol) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let chat_msg = ChatMessage { mentions: vec![bob, carol, bob], ..chat("@Bob @Carol @Bob") };
+        let message = match manager.stamp_chat_message(session_id, sender("Alice"), chat_msg, None) {
+            Some(StampedMessage::Fresh(message)) => message,
+            other => panic!("Expected a fresh message, got {:?}", other),
+        };
+
+        assert_eq!(message.mentions, vec![bob, carol]);
+    }
+
+    #[test]
+    fn test_mentions_are_broadcast() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (conn, mut rx) = create_connection(Uuid::new_v4(), "Alice");
+        manager.insert(session_id, 1, conn, None);
+        let bob = Uuid::new_v4();
+
+        let chat_msg = ChatMessage { mentions: vec![bob], ..chat("@Bob") };
+        if let Some(StampedMessage::Fresh(message)) = manager.stamp_chat_message(session_id, sender("Carol"), chat_msg, None) {
+            manager.broadcast_chat_message(session_id, message, None);
+        }
+
+        let frames = frames_of_type(&mut rx, "chat_message");
+        assert_eq!(frames[0]["mentions"][0], bob.to_string());
+    }
+
+    #[test]
+    fn test_is_user_connected_tracks_connections() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(user_id, "Alice");
+
+        assert!(!manager.is_user_connected(session_id, user_id));
+        manager.insert(session_id, 1, conn, None);
+        assert!(manager.is_user_connected(session_id, user_id));
+        manager.remove(session_id, 1);
+        assert!(!manager.is_user_connected(session_id, user_id));
+    }
+}
+
+#[cfg(test)]
+mod direct_message_tests {
+    use super::*;
+    use chrono::Utc;
+    use realtime_service::model::chat_message::DirectMessage;
+
+    #[test]
+    fn test_direct_message_reaches_only_recipient_and_sender_devices() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (mentee_id, coach_id) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let (phone, mut phone_rx) = create_connection(mentee_id, "Mentee");
+        let (laptop, mut laptop_rx) = create_connection(mentee_id, "Mentee");
+        let (coach, mut coach_rx) = create_connection(coach_id, "Coach");
+        let (other, mut other_rx) = create_connection(Uuid::new_v4(), "Other");
+        manager.insert(session_id, 1, phone, None);
+        manager.insert(session_id, 2, laptop, None);
+        manager.insert(session_id, 3, coach, None);
+        manager.insert(session_id, 4, other, None);
+
+        let message = DirectMessage {
+            id: Uuid::new_v4(),
+            sent_at: Utc::now(),
+            sender: SenderInfo { id: mentee_id, name: "Mentee".to_string() },
+            to_user_id: coach_id,
+            content: "Can we talk after?".to_string(),
+            links: Vec::new(),
+        };
+        manager.deliver_direct_message(session_id, &message, Some(1));
+
+        assert!(frames_of_type(&mut phone_rx, "direct_message").is_empty(), "Sending device is acked instead");
+        assert_eq!(frames_of_type(&mut laptop_rx, "direct_message").len(), 1);
+        let delivered = frames_of_type(&mut coach_rx, "direct_message");
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0]["content"], "Can we talk after?");
+        assert!(frames_of_type(&mut other_rx, "direct_message").is_empty());
+    }
+
+    #[test]
+    fn test_direct_messages_are_not_replayed() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let (sender_id, recipient_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let (conn, _rx) = create_connection(sender_id, "Mentee");
+        manager.insert(session_id, 1, conn, None);
+
+        let message = DirectMessage {
+            id: Uuid::new_v4(),
+            sent_at: Utc::now(),
+            sender: SenderInfo { id: sender_id, name: "Mentee".to_string() },
+            to_user_id: recipient_id,
+            content: "private".to_string(),
+            links: Vec::new(),
+        };
+        manager.deliver_direct_message(session_id, &message, Some(1));
+
+        let (late, _late_rx) = create_connection(recipient_id, "Coach");
+        assert!(replayed(manager.insert(session_id, 2, late, None)).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod disconnect_tests {
+    use super::*;
+    use realtime_service::model::protocol::CloseCode;
+    use tokio::sync::oneshot;
+
+    fn closable_connection(user_id: Uuid, name: &str) -> (Connection, oneshot::Receiver<CloseCode>) {
+        let (conn, _rx) = create_connection(user_id, name);
+        let (close_tx, close_rx) = oneshot::channel();
+        (Connection { closer: Some(close_tx), ..conn }, close_rx)
+    }
+
+    #[test]
+    fn test_disconnect_user_closes_every_device() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let mentee_id = Uuid::new_v4();
+
+        let (phone, mut phone_close) = closable_connection(mentee_id, "Mentee");
+        let (laptop, mut laptop_close) = closable_connection(mentee_id, "Mentee");
+        let (other, mut other_close) = closable_connection(Uuid::new_v4(), "Other");
+        manager.insert(session_id, 1, phone, None);
+        manager.insert(session_id, 2, laptop, None);
+        manager.insert(session_id, 3, other, None);
+
+        assert_eq!(manager.disconnect_user(session_id, mentee_id, CloseCode::Kicked), 2);
+
+        assert_eq!(phone_close.try_recv(), Ok(CloseCode::Kicked));
+        assert_eq!(laptop_close.try_recv(), Ok(CloseCode::Kicked));
+        assert!(other_close.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_disconnect_user_signals_once() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        let (conn, _close) = closable_connection(user_id, "Mentee");
+        manager.insert(session_id, 1, conn, None);
+
+        assert_eq!(manager.disconnect_user(session_id, user_id, CloseCode::Kicked), 1);
+        assert_eq!(manager.disconnect_user(session_id, user_id, CloseCode::Kicked), 0);
+        assert_eq!(manager.disconnect_user(Uuid::new_v4(), user_id, CloseCode::Kicked), 0);
+    }
+}
+
+#[cfg(test)]
+mod end_session_tests {
+    use super::*;
+    use realtime_service::model::protocol::CloseCode;
+    use tokio::sync::oneshot;
+
+    #[test]
+    fn test_end_session_notifies_closes_and_drops() {
+        let manager = SessionManager::new();
+        let session_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        let (conn, mut rx) = create_connection(user_id, "Alice");
+        let (close_tx, mut close_rx) = oneshot::channel();
+        manager.insert(session_id, 1, Connection { closer: Some(close_tx), ..conn }, None);
+        stamp_fresh(&manager, session_id, sender("Alice"), "before the end");
+
+        let closed = manager.end_session(session_id, r#"{"v":1,"type":"session_ended"}"#);
+
+        assert_eq!(closed, 1);
+        assert_eq!(frames_of_type(&mut rx, "session_ended").len(), 1);
+        assert_eq!(close_rx.try_recv(), Ok(CloseCode::SessionEnded));
+        assert!(!manager.is_user_connected(session_id, user_id));
+
+        let (late, _late_rx) = create_connection(user_id, "Alice");
+        assert!(replayed(manager.insert(session_id, 2, late, None)).is_empty(), "No state survives the end");
+    }
+
+    #[test]
+    fn test_end_unknown_session_is_noop() {
+        let manager = SessionManager::new();
+        assert_eq!(manager.end_session(Uuid::new_v4(), "{}"), 0);
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::*;
+    use realtime_service::services::rate_limiter::RateLimit;
+
+    fn limited_manager(user_burst: f64, session_burst: f64) -> SessionManager {
+        SessionManager::with_config(SessionManagerConfig {
+            user_rate_limit: RateLimit { burst: user_burst, per_second: 0.5 },
+            session_rate_limit: RateLimit { burst: session_burst, per_second: 0.5 },
+            ..SessionManagerConfig::default()
+        })
+    }
+
+    fn live_session(manager: &SessionManager) -> Uuid {
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Coach");
+        manager.insert(session_id, 1, conn, None);
+        session_id
+    }
+
+    #[test]
+    fn test_user_limit_applies_per_user() {
+        let manager = limited_manager(2.0, 100.0);
+        let session_id = live_session(&manager);
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+
+        assert!(manager.check_rate(session_id, alice, None).is_ok());
+        assert!(manager.check_rate(session_id, alice, None).is_ok());
+        let retry_after = manager.check_rate(session_id, alice, None).unwrap_err();
+
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(2));
+        assert!(manager.check_rate(session_id, bob, None).is_ok(), "Other users keep their own budget");
+    }
+
+    #[test]
+    fn test_session_limit_applies_to_everyone() {
+        let manager = limited_manager(100.0, 2.0);
+        let session_id = live_session(&manager);
+
+        assert!(manager.check_rate(session_id, Uuid::new_v4(), None).is_ok());
+        assert!(manager.check_rate(session_id, Uuid::new_v4(), None).is_ok());
+        assert!(manager.check_rate(session_id, Uuid::new_v4(), None).is_err());
+    }
+
+    #[test]
+    fn test_actions_have_their_own_budget() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            user_rate_limit: RateLimit { burst: 1.0, per_second: 0.5 },
+            action_rate_limit: RateLimit { burst: 2.0, per_second: 0.5 },
+            ..SessionManagerConfig::default()
+        });
+        let session_id = live_session(&manager);
+        let alice = Uuid::new_v4();
+
+        assert!(manager.check_action_rate(session_id, alice).is_ok());
+        assert!(manager.check_action_rate(session_id, alice).is_ok());
+        assert!(manager.check_action_rate(session_id, alice).is_err());
+
+        assert!(manager.check_rate(session_id, alice, None).is_ok(), "Actions do not spend posting tokens");
+        assert!(manager.check_action_rate(session_id, Uuid::new_v4()).is_ok());
+    }
+
+    #[test]
+    fn test_reports_have_their_own_budget() {
+        let manager = SessionManager::with_config(SessionManagerConfig {
+            report_rate_limit: RateLimit { burst: 1.0, per_second: 0.5 },
+            ..SessionManagerConfig::default()
+        });
+        let session_id = live_session(&manager);
+        let alice = Uuid::new_v4();
+
+        assert!(manager.check_rate(session_id, alice, Some(Duration::from_secs(60))).is_ok());
+        assert!(manager.check_report_rate(session_id, alice).is_ok(), "Slow mode does not hold back reports");
+        assert!(manager.check_report_rate(session_id, alice).is_err());
+        assert!(manager.check_action_rate(session_id, alice).is_ok());
+    }
+
+    #[test]
+    fn test_slow_mode_spaces_out_posts() {
+        let manager = limited_manager(100.0, 100.0);
+        let session_id = live_session(&manager);
+        let (mentee, coach) = (Uuid::new_v4(), Uuid::new_v4());
+        let slow_mode = Some(Duration::from_secs(30));
+
+        assert!(manager.check_rate(session_id, mentee, slow_mode).is_ok());
+        let retry_after = manager.check_rate(session_id, mentee, slow_mode).unwrap_err();
+        assert!(retry_after > Duration::from_secs(29));
+
+        assert!(manager.check_rate(session_id, coach, None).is_ok());
+        assert!(manager.check_rate(session_id, coach, None).is_ok(), "Exempt users pass no interval");
+
+        assert!(manager.check_rate(session_id, mentee, None).is_ok());
+    }
+}
+
+
+#[cfg(test)]
+mod report_context_tests {
+    use super::*;
+    use chrono::Utc;
+    use realtime_service::model::chat_message::DirectMessage;
+
+    fn conversation(manager: &SessionManager, senders: &[&SenderInfo]) -> (Uuid, Vec<BroadcastMessage>) {
+        let session_id = Uuid::new_v4();
+        let (conn, _rx) = create_connection(Uuid::new_v4(), "Coach");
+        manager.insert(session_id, 1, conn, None);
+
+        let messages = senders.iter().enumerate().map(|(i, sender)| {
+            let message = stamp_fresh(manager, session_id, (*sender).clone(), &format!("m{}", i));
+            manager.broadcast_chat_message(session_id, message.clone(), None);
+            message
+        }).collect();
+
+        (session_id, messages)
+    }
+
+    #[test]
+    fn test_message_report_captures_surrounding_messages() {
+        let manager = SessionManager::new();
+        let (alice, bob) = (sender("Alice"), sender("Bob"));
+        let senders: Vec<&SenderInfo> = (0..15).map(|i| if i == 7 { &bob } else { &alice }).collect();
+        let (session_id, messages) = conversation(&manager, &senders);
+
+        let report = manager.report_context(session_id, alice.id, Some(messages[7].id), None).unwrap();
+
+        assert_eq!(report.reported_user_id, bob.id);
+        assert_eq!(report.message.unwrap().content, "m7");
+        let context: Vec<&str> = report.context.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(context, ["m2", "m3", "m4", "m5", "m6", "m8", "m9", "m10", "m11", "m12"]);
+    }
+
+    #[test]
+    fn test_user_report_captures_their_latest_messages() {
+        let manager = SessionManager::new();
+        let (alice, bob) = (sender("Alice"), sender("Bob"));
+        let (session_id, _) = conversation(&manager, &[&bob, &alice, &bob]);
+
+        let report = manager.report_context(session_id, alice.id, None, Some(bob.id)).unwrap();
+
+        assert!(report.message.is_none());
+        assert_eq!(report.reported_user_id, bob.id);
+        assert_eq!(report.context.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), ["m0", "m2"]);
+    }
+
+    #[test]
+    fn test_unbuffered_message_cannot_be_reported() {
+        let manager = SessionManager::new();
+        let (session_id, _) = conversation(&manager, &[&sender("Alice")]);
+
+        assert!(manager.report_context(session_id, Uuid::new_v4(), Some(Uuid::new_v4()), None).is_none());
+        assert!(manager.report_context(session_id, Uuid::new_v4(), None, None).is_none());
+    }
+
+    #[test]
+    fn test_direct_message_can_be_reported_by_its_recipient_only() {
+        let manager = SessionManager::new();
+        let (alice, bob) = (sender("Alice"), sender("Bob"));
+        let (session_id, _) = conversation(&manager, &[&alice]);
+        let message = DirectMessage {
+            id: Uuid::new_v4(),
+            sent_at: Utc::now(),
+            sender: bob.clone(),
+            to_user_id: alice.id,
+            content: "psst".to_string(),
+            links: Vec::new(),
+        };
+        manager.deliver_direct_message(session_id, &message, None);
+
+        let report = manager.report_context(session_id, alice.id, Some(message.id), None).unwrap();
+        assert_eq!(report.reported_user_id, bob.id);
+        assert_eq!(report.direct_message.unwrap().content, "psst");
+        assert!(report.message.is_none() && report.context.is_empty());
+
+        assert!(manager.report_context(session_id, Uuid::new_v4(), Some(message.id), None).is_none());
+    }
+}
\ No newline at end of file
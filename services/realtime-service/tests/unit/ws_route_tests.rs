@@ -0,0 +1,434 @@
+use actix_web::{web, App, HttpServer};
+use futures_util::{SinkExt, StreamExt};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use realtime_service::api::ws_handler::ws_route;
+use realtime_service::auth::jwt::Claims;
+use realtime_service::events::nats_publisher::NatsPublisher;
+use realtime_service::model::protocol::CloseCode;
+use realtime_service::services::content_validation::ContentLimits;
+use realtime_service::services::membership::MembershipLookup;
+use realtime_service::services::message_filter::{FilterAction, FilterPipeline, WordListFilter};
+use realtime_service::services::session_manager::SessionManager;
+use realtime_service::services::session_registry::SessionRegistry;
+use serde_json::{json, Value};
+use std::sync::Once;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+const JWT_SECRET: &str = "ws-route-tests-secret";
+const FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+struct TestServer {
+    port: u16,
+    registry: web::Data<SessionRegistry>,
+}
+
+fn init_secret() {
+    static INIT: Once = Once::new();
+    // Every test sets the same value before any token is validated.
+    INIT.call_once(|| unsafe { std::env::set_var("JWT_SECRET", JWT_SECRET) });
+}
+
+/// Runs `ws_route` on an ephemeral port. The NATS client never connects;
+/// publishes are buffered, which is enough for the handlers to succeed.
+async fn start_server(filters: FilterPipeline) -> TestServer {
+    init_secret();
+
+    let manager = web::Data::new(SessionManager::new());
+    let registry = web::Data::new(SessionRegistry::new());
+    let lookup = web::Data::new(MembershipLookup::new(None, None, Duration::from_secs(1)));
+    let limits = web::Data::new(ContentLimits::default());
+    let filters = web::Data::new(filters);
+    let client = async_nats::ConnectOptions::new()
+        .retry_on_initial_connect()
+        .connect("nats://127.0.0.1:1")
+        .await
+        .unwrap();
+    let publisher = web::Data::new(NatsPublisher { client });
+
+    let app_registry = registry.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(manager.clone())
+            .app_data(app_registry.clone())
+            .app_data(lookup.clone())
+            .app_data(limits.clone())
+            .app_data(filters.clone())
+            .app_data(publisher.clone())
+            .route("/v1/ws/{session_id}", web::get().to(ws_route))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+
+    let port = server.addrs()[0].port();
+    actix_web::rt::spawn(server.run());
+
+    TestServer { port, registry }
+}
+
+fn token(user_id: Uuid, name: &str) -> String {
+    let claims = Claims {
+        sub: user_id,
+        name: name.to_string(),
+        email: format!("{}@example.com", name.to_lowercase()),
+        exp: usize::MAX / 2,
+    };
+    encode(&Header::default(), &claims, &EncodingKey::from_secret(JWT_SECRET.as_ref())).unwrap()
+}
+
+async fn connect(server: &TestServer, session_id: Uuid, user_id: Uuid, name: &str) -> Result<Client, tungstenite::Error> {
+    let url = format!("ws://127.0.0.1:{}/v1/ws/{}?token={}", server.port, session_id, token(user_id, name));
+    connect_async(url).await.map(|(ws, _)| ws)
+}
+
+fn rejection_status(result: Result<Client, tungstenite::Error>) -> u16 {
+    match result {
+        Err(tungstenite::Error::Http(response)) => response.status().as_u16(),
+        Err(e) => panic!("Expected an HTTP rejection, got {:?}", e),
+        Ok(_) => panic!("Expected the upgrade to be refused"),
+    }
+}
+
+async fn send(ws: &mut Client, frame: Value) {
+    ws.send(Message::Text(frame.to_string())).await.unwrap();
+}
+
+/// Next text frame of `frame_type`, skipping presence and other noise.
+async fn next_frame(ws: &mut Client, frame_type: &str) -> Value {
+    loop {
+        let message = tokio::time::timeout(FRAME_TIMEOUT, ws.next())
+            .await
+            .unwrap_or_else(|_| panic!("Timed out waiting for {}", frame_type))
+            .expect("Connection closed")
+            .unwrap();
+
+        if let Message::Text(text) = message {
+            let json: Value = serde_json::from_str(&text).unwrap();
+            if json["type"] == frame_type {
+                return json;
+            }
+        }
+    }
+}
+
+/// Waits briefly and reports whether a frame of `frame_type` arrived.
+async fn receives(ws: &mut Client, frame_type: &str) -> bool {
+    let deadline = tokio::time::sleep(Duration::from_millis(300));
+    tokio::pin!(deadline);
+
+    loop {
+        tokio::select! {
+            _ = &mut deadline => return false,
+            message = ws.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let json: Value = serde_json::from_str(&text).unwrap();
+                    if json["type"] == frame_type {
+                        return true;
+                    }
+                }
+                Some(Ok(_)) => {}
+                _ => return false,
+            },
+        }
+    }
+}
+
+/// A created session with its coach and one mentee on the member list.
+fn live_session(server: &TestServer) -> (Uuid, Uuid, Uuid) {
+    let (session_id, coach_id, mentee_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    server.registry.record_created(session_id, coach_id);
+    server.registry.record_joined(session_id, mentee_id);
+    (session_id, coach_id, mentee_id)
+}
+
+#[cfg(test)]
+mod upgrade_tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_invalid_token_is_unauthorized() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let url = format!("ws://127.0.0.1:{}/v1/ws/{}?token=garbage", server.port, Uuid::new_v4());
+
+        let result = connect_async(url).await.map(|(ws, _)| ws);
+
+        assert_eq!(rejection_status(result), 401);
+    }
+
+    #[actix_web::test]
+    async fn test_non_member_is_forbidden() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, _, _) = live_session(&server);
+
+        assert_eq!(rejection_status(connect(&server, session_id, Uuid::new_v4(), "Mallory").await), 403);
+    }
+
+    #[actix_web::test]
+    async fn test_ended_session_is_gone() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, _, mentee_id) = live_session(&server);
+        server.registry.record_ended(session_id);
+
+        assert_eq!(rejection_status(connect(&server, session_id, mentee_id, "Mentee").await), 410);
+    }
+
+    #[actix_web::test]
+    async fn test_banned_member_is_forbidden() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, _, mentee_id) = live_session(&server);
+        server.registry.ban(session_id, mentee_id);
+
+        assert_eq!(rejection_status(connect(&server, session_id, mentee_id, "Mentee").await), 403);
+    }
+}
+
+#[cfg(test)]
+mod chat_flow_tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_chat_is_acked_and_broadcast() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut coach = connect(&server, session_id, coach_id, "Coach").await.unwrap();
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+        next_frame(&mut coach, "participant_joined").await;
+
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-1", "content": " <b>Hi</b> coach "})).await;
+
+        let ack = next_frame(&mut mentee, "ack").await;
+        assert_eq!(ack["client_msg_id"], "c-1");
+        assert_eq!(ack["seq"], 1);
+
+        let broadcast = next_frame(&mut coach, "chat_message").await;
+        assert_eq!(broadcast["content"], "Hi coach");
+        assert_eq!(broadcast["id"], ack["message_id"]);
+    }
+
+    #[actix_web::test]
+    async fn test_retry_of_accepted_message_is_acked_even_when_muted() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut coach = connect(&server, session_id, coach_id, "Coach").await.unwrap();
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+        next_frame(&mut coach, "participant_joined").await;
+
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-1", "content": "hello"})).await;
+        let ack = next_frame(&mut mentee, "ack").await;
+        next_frame(&mut coach, "chat_message").await;
+        send(&mut coach, json!({"type": "mute_user", "user_id": mentee_id, "duration_secs": 60})).await;
+        next_frame(&mut mentee, "moderation").await;
+
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-1", "content": "hello"})).await;
+
+        let retry = next_frame(&mut mentee, "ack").await;
+        assert_eq!(retry["message_id"], ack["message_id"]);
+        assert!(!receives(&mut coach, "chat_message").await, "A retry is never broadcast twice");
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_content_is_nacked() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-4", "content": "[x](javascript:alert(1))"})).await;
+        let nack = next_frame(&mut mentee, "nack").await;
+        assert_eq!((nack["code"].as_str(), nack["client_msg_id"].as_str()), (Some("unsafe_link"), Some("c-4")));
+
+        send(&mut mentee, json!({"type": "direct_message", "to_user_id": coach_id, "client_msg_id": "c-5", "content": "<b></b>"})).await;
+        let nack = next_frame(&mut mentee, "nack").await;
+        assert_eq!((nack["code"].as_str(), nack["client_msg_id"].as_str()), (Some("empty_content"), Some("c-5")));
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_posts_are_charged_and_disconnect() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, _, mentee_id) = live_session(&server);
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+
+        for i in 0..5 {
+            send(&mut mentee, json!({"type": "chat", "client_msg_id": format!("c-{}", i), "content": "<b></b>"})).await;
+            assert_eq!(next_frame(&mut mentee, "nack").await["code"], "empty_content");
+        }
+
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-5", "content": "hello"})).await;
+        let nack = next_frame(&mut mentee, "nack").await;
+        assert_eq!(nack["code"], "rate_limited");
+        assert!(nack["retry_after_ms"].as_u64().is_some());
+
+        for i in 6..10 {
+            send(&mut mentee, json!({"type": "chat", "client_msg_id": format!("c-{}", i), "content": "hello"})).await;
+            next_frame(&mut mentee, "nack").await;
+        }
+
+        let closed = tokio::time::timeout(FRAME_TIMEOUT, async {
+            while let Some(Ok(message)) = mentee.next().await {
+                if let Message::Close(frame) = message {
+                    return frame.map(|frame| u16::from(frame.code));
+                }
+            }
+            None
+        });
+        assert_eq!(closed.await.unwrap(), Some(CloseCode::RateLimited.code()));
+    }
+
+    #[actix_web::test]
+    async fn test_too_many_mentions_are_nacked() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+
+        let mentions = vec![coach_id; 21];
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-9", "content": "hey", "mentions": mentions})).await;
+
+        let nack = next_frame(&mut mentee, "nack").await;
+        assert_eq!((nack["code"].as_str(), nack["client_msg_id"].as_str()), (Some("invalid_mention"), Some("c-9")));
+    }
+
+    #[actix_web::test]
+    async fn test_filter_rejection_is_nacked() {
+        let filters = FilterPipeline::new(vec![Box::new(WordListFilter::new(["darn"], FilterAction::Reject))]);
+        let server = start_server(filters).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut coach = connect(&server, session_id, coach_id, "Coach").await.unwrap();
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+        next_frame(&mut coach, "participant_joined").await;
+
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-6", "content": "darn it"})).await;
+        let nack = next_frame(&mut mentee, "nack").await;
+        assert_eq!((nack["code"].as_str(), nack["client_msg_id"].as_str()), (Some("content_rejected"), Some("c-6")));
+
+        send(&mut mentee, json!({"type": "direct_message", "to_user_id": coach_id, "client_msg_id": "c-7", "content": "DARN"})).await;
+        assert_eq!(next_frame(&mut mentee, "nack").await["code"], "content_rejected");
+        assert!(!receives(&mut coach, "direct_message").await);
+    }
+
+    #[actix_web::test]
+    async fn test_malformed_direct_message_is_not_broadcast() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut coach = connect(&server, session_id, coach_id, "Coach").await.unwrap();
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+        next_frame(&mut coach, "participant_joined").await;
+
+        send(&mut mentee, json!({"type": "direct_message", "to_user_id": "nope", "client_msg_id": "c-2", "content": "secret"})).await;
+
+        let error = next_frame(&mut mentee, "error").await;
+        assert_eq!(error["client_msg_id"], "c-2");
+        assert!(!receives(&mut coach, "chat_message").await, "A broken DM must never be posted publicly");
+    }
+
+    #[actix_web::test]
+    async fn test_direct_message_reaches_only_the_recipient() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let other_id = Uuid::new_v4();
+        server.registry.record_joined(session_id, other_id);
+        let mut coach = connect(&server, session_id, coach_id, "Coach").await.unwrap();
+        let mut other = connect(&server, session_id, other_id, "Other").await.unwrap();
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+        next_frame(&mut coach, "participant_joined").await;
+
+        send(&mut mentee, json!({"type": "direct_message", "to_user_id": coach_id, "content": "psst"})).await;
+
+        assert!(next_frame(&mut mentee, "ack").await["seq"].is_null());
+        assert_eq!(next_frame(&mut coach, "direct_message").await["content"], "psst");
+        assert!(!receives(&mut other, "direct_message").await);
+    }
+
+    #[actix_web::test]
+    async fn test_direct_message_retry_is_delivered_once() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut coach = connect(&server, session_id, coach_id, "Coach").await.unwrap();
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+        next_frame(&mut coach, "participant_joined").await;
+
+        let frame = json!({"type": "direct_message", "to_user_id": coach_id, "client_msg_id": "d-1", "content": "psst"});
+        send(&mut mentee, frame.clone()).await;
+        let ack = next_frame(&mut mentee, "ack").await;
+        next_frame(&mut coach, "direct_message").await;
+
+        send(&mut mentee, frame).await;
+
+        assert_eq!(next_frame(&mut mentee, "ack").await["message_id"], ack["message_id"]);
+        assert!(!receives(&mut coach, "direct_message").await, "A retry is never delivered twice");
+    }
+
+    #[actix_web::test]
+    async fn test_chat_settings_survive_an_empty_room() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut coach = connect(&server, session_id, coach_id, "Coach").await.unwrap();
+
+        send(&mut coach, json!({"type": "set_chat_mode", "mode": "announcement_only"})).await;
+        assert_eq!(next_frame(&mut coach, "chat_settings").await["mode"], "announcement_only");
+        coach.close(None).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+        assert_eq!(next_frame(&mut mentee, "chat_settings").await["mode"], "announcement_only");
+
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-3", "content": "hello?"})).await;
+        assert_eq!(next_frame(&mut mentee, "nack").await["code"], "chat_restricted");
+    }
+
+    #[actix_web::test]
+    async fn test_report_reason_is_sanitized() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+
+        send(&mut mentee, json!({"type": "report", "user_id": coach_id, "reason": "<b></b>", "client_msg_id": "r-1"})).await;
+        assert_eq!(next_frame(&mut mentee, "nack").await["code"], "invalid_report");
+
+        send(&mut mentee, json!({"type": "report", "user_id": coach_id, "reason": "rude\u{7}", "client_msg_id": "r-2"})).await;
+        assert_eq!(next_frame(&mut mentee, "nack").await["code"], "invalid_report");
+
+        send(&mut mentee, json!({"type": "report", "user_id": coach_id, "reason": "<i>rude</i>", "client_msg_id": "r-3"})).await;
+        assert_eq!(next_frame(&mut mentee, "ack").await["client_msg_id"], "r-3");
+    }
+
+    #[actix_web::test]
+    async fn test_deletes_are_rate_limited() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, _, mentee_id) = live_session(&server);
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+
+        for i in 0..10 {
+            send(&mut mentee, json!({"type": "delete_message", "message_id": Uuid::new_v4(), "client_msg_id": format!("d-{}", i)})).await;
+            assert_eq!(next_frame(&mut mentee, "error").await["code"], "message_not_found");
+        }
+
+        send(&mut mentee, json!({"type": "delete_message", "message_id": Uuid::new_v4(), "client_msg_id": "d-10"})).await;
+        let error = next_frame(&mut mentee, "error").await;
+        assert_eq!(error["code"], "rate_limited");
+        assert!(error["retry_after_ms"].as_u64().is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_edits_respect_chat_mode() {
+        let server = start_server(FilterPipeline::new(Vec::new())).await;
+        let (session_id, coach_id, mentee_id) = live_session(&server);
+        let mut coach = connect(&server, session_id, coach_id, "Coach").await.unwrap();
+        let mut mentee = connect(&server, session_id, mentee_id, "Mentee").await.unwrap();
+        next_frame(&mut coach, "participant_joined").await;
+
+        send(&mut mentee, json!({"type": "chat", "client_msg_id": "c-8", "content": "first"})).await;
+        let message_id = next_frame(&mut mentee, "ack").await["message_id"].clone();
+        send(&mut coach, json!({"type": "set_chat_mode", "mode": "disabled"})).await;
+        next_frame(&mut mentee, "chat_settings").await;
+
+        send(&mut mentee, json!({"type": "edit_message", "message_id": message_id, "content": "sneaky", "client_msg_id": "e-1"})).await;
+
+        assert_eq!(next_frame(&mut mentee, "error").await["code"], "chat_restricted");
+        assert!(!receives(&mut coach, "message_edited").await);
+    }
+}
\ No newline at end of file